@@ -0,0 +1,85 @@
+use crate::{dirs, strerr::Strerr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// Seconds into the track.
+    pub position: f64,
+    /// Length of the track in seconds, for listing progress.
+    pub duration: f64,
+    pub speed: f32,
+    pub finished: bool,
+}
+
+/// Remembered positions of long tracks such as audiobooks and podcasts, kept
+/// in the data directory and keyed by canonical path.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Bookmarks {
+    tracks: BTreeMap<PathBuf, Bookmark>,
+    /// Whether anything changed since `take_changed` last asked.
+    #[serde(skip)]
+    changed: bool,
+}
+
+pub fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Bookmarks {
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("bookmarks.toml"))
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        Ok(dirs::read_toml(&path)?.unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no data directory")?;
+        let text = toml::to_string(self).strerr()?;
+
+        dirs::write_atomic(&path, text)
+    }
+
+    /// Whether there is anything new to save, clearing the flag.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// The bookmark to resume `path` from, unless it was finished.
+    pub fn get(&self, path: &Path) -> Option<&Bookmark> {
+        self.tracks
+            .get(&canonical(path))
+            .filter(|bookmark| !bookmark.finished)
+    }
+
+    pub fn remember(&mut self, path: &Path, bookmark: Bookmark) {
+        self.changed |= self.tracks.insert(canonical(path), bookmark) != Some(bookmark);
+    }
+
+    /// Marks `path` as finished, so it starts from the beginning next time.
+    pub fn finish(&mut self, path: &Path) {
+        if let Some(bookmark) = self.tracks.get_mut(&canonical(path))
+            && !bookmark.finished
+        {
+            bookmark.position = 0.0;
+            bookmark.finished = true;
+            self.changed = true;
+        }
+    }
+
+    pub fn in_progress(&self) -> impl Iterator<Item = (&Path, &Bookmark)> {
+        self.tracks
+            .iter()
+            .filter(|(_, bookmark)| !bookmark.finished)
+            .map(|(path, bookmark)| (path.as_path(), bookmark))
+    }
+}
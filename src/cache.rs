@@ -0,0 +1,60 @@
+use crate::{dirs, strerr::Strerr};
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// FNV-1a, chosen over `DefaultHasher` because the key has to stay stable
+/// across builds.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>, hash: u64) -> u64 {
+    bytes.into_iter().fold(hash, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Identifies the content of `path` by its location, size and modification time.
+pub fn key<P>(path: P) -> Result<u64, String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().canonicalize().strerr()?;
+    let metadata = path.metadata().strerr()?;
+    let mtime = metadata
+        .modified()
+        .strerr()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let hash = fnv1a(path.to_string_lossy().bytes(), 0xcbf29ce484222325);
+    let hash = fnv1a(metadata.len().to_le_bytes(), hash);
+    Ok(fnv1a(mtime.to_le_bytes(), hash))
+}
+
+fn entry<P>(kind: &str, path: P) -> Result<PathBuf, String>
+where
+    P: AsRef<Path>,
+{
+    let dir = dirs::cache_dir().ok_or("no cache directory")?.join(kind);
+    Ok(dir.join(format!("{:016x}", key(path)?)))
+}
+
+pub fn load<P>(kind: &str, path: P) -> Option<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    std::fs::read(entry(kind, path).ok()?).ok()
+}
+
+pub fn store<P>(kind: &str, path: P, bytes: &[u8]) -> Result<(), String>
+where
+    P: AsRef<Path>,
+{
+    let entry = entry(kind, path)?;
+
+    if let Some(dir) = entry.parent() {
+        std::fs::create_dir_all(dir).strerr()?;
+    }
+
+    std::fs::write(entry, bytes).strerr()
+}
@@ -0,0 +1,379 @@
+use crate::{
+    id3::{self, Frame, Tag},
+    strerr::Strerr,
+};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+pub struct Chapter {
+    /// Seconds into the file.
+    pub start: f64,
+    pub title: String,
+}
+
+/// Chapters of an MP3 (ID3v2 `CHAP`/`CTOC` frames) or MP4 (chapter track or Nero `chpl` atom)
+/// file, in order. Files without any have none, and neither do ID3v2 tags of a
+/// kind `Tag` cannot read.
+pub fn read(path: &Path) -> Result<Vec<Chapter>, String> {
+    let mut head = [0; 10];
+
+    if File::open(path).strerr()?.read_exact(&mut head).is_err() {
+        return Ok(Vec::new());
+    }
+
+    if &head[..3] == b"ID3" {
+        if !id3::is_supported(&head) {
+            return Ok(Vec::new());
+        }
+
+        Ok(Tag::read(path)?.map_or_else(Vec::new, |tag| from_id3(&tag)))
+    } else if &head[4..8] == b"ftyp" {
+        from_mp4(path)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Null-terminated ISO-8859-1, as element IDs are.
+fn split_id(bytes: &[u8]) -> (String, &[u8]) {
+    id3::split_text(0, bytes)
+}
+
+fn title(major: u8, embedded: &[u8]) -> Option<String> {
+    id3::parse_frames(major, embedded)
+        .ok()?
+        .into_iter()
+        .find(|frame| &frame.id == b"TIT2")
+        .and_then(|frame| {
+            let (&encoding, text) = frame.data.split_first()?;
+            Some(id3::split_text(encoding, text).0)
+        })
+}
+
+/// Chapters listed by the top-level `CTOC` in its order, or else every `CHAP` by start time.
+fn from_id3(tag: &Tag) -> Vec<Chapter> {
+    let frames = |id: [u8; 4]| {
+        tag.frames
+            .iter()
+            .filter(move |frame: &&Frame| frame.id == id)
+    };
+
+    let mut chapters: Vec<(String, Chapter)> = frames(*b"CHAP")
+        .filter_map(|frame| {
+            let (id, rest) = split_id(&frame.data);
+            let start = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+            let title = title(tag.major, rest.get(16..)?).unwrap_or_else(|| id.clone());
+
+            Some((
+                id,
+                Chapter {
+                    start: start as f64 / 1000.0,
+                    title,
+                },
+            ))
+        })
+        .collect();
+
+    let toc = frames(*b"CTOC").find_map(|frame| {
+        let (_, rest) = split_id(&frame.data);
+        let (&flags, rest) = rest.split_first()?;
+        let (&count, mut rest) = rest.split_first()?;
+        let mut children = Vec::new();
+
+        for _ in 0..count {
+            let (child, after) = split_id(rest);
+            children.push(child);
+            rest = after;
+        }
+
+        (flags & 0x02 != 0).then_some(children)
+    });
+
+    match toc {
+        Some(children) => children
+            .iter()
+            .filter_map(|child| {
+                let index = chapters.iter().position(|(id, _)| id == child)?;
+                Some(chapters.swap_remove(index).1)
+            })
+            .collect(),
+        None => {
+            chapters.sort_by(|(_, a), (_, b)| a.start.total_cmp(&b.start));
+            chapters.into_iter().map(|(_, chapter)| chapter).collect()
+        }
+    }
+}
+
+/// The atoms in `bytes` as kinds and contents, stopping at the first broken one.
+fn atoms(mut bytes: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut atoms = Vec::new();
+
+    while let Some(header) = bytes.get(..8) {
+        let (header_len, size) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                0 => (8, bytes.len() as u64),
+                1 => match bytes.get(8..16).and_then(|size| size.try_into().ok()) {
+                    Some(size) => (16, u64::from_be_bytes(size)),
+                    None => break,
+                },
+                size => (8, size as u64),
+            };
+        let Some(body) = usize::try_from(size)
+            .ok()
+            .and_then(|size| bytes.get(header_len..size))
+        else {
+            break;
+        };
+
+        atoms.push(([header[4], header[5], header[6], header[7]], body));
+        bytes = &bytes[header_len + body.len()..];
+    }
+
+    atoms
+}
+
+/// The contents of the first atom `kind` in `bytes`.
+fn atom<'a>(bytes: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(bytes)
+        .into_iter()
+        .find_map(|(found, body)| (&found == kind).then_some(body))
+}
+
+/// Follows `path` down through nested atoms.
+fn find<'a>(bytes: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(bytes, |bytes, kind| atom(bytes, kind))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// A field after the creation and modification times, which are 64-bit from version 1 on.
+fn after_times(body: &[u8]) -> Option<u32> {
+    match body.first()? {
+        0 => be_u32(body, 12),
+        _ => be_u32(body, 20),
+    }
+}
+
+/// The `moov` atom. Only it is read, as the media data around it can be huge.
+fn read_moov(file: &mut File) -> Result<Option<Vec<u8>>, String> {
+    let len = file.metadata().strerr()?.len();
+    let mut pos = 0;
+
+    loop {
+        let mut header = [0; 16];
+
+        if len.saturating_sub(pos) < 8 {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(pos)).strerr()?;
+        file.read_exact(&mut header[..8]).strerr()?;
+
+        let (header_len, size) = match u32::from_be_bytes(header[..4].try_into().strerr()?) {
+            0 => (8, len - pos),
+            1 => {
+                file.read_exact(&mut header[8..]).strerr()?;
+                (16, u64::from_be_bytes(header[8..].try_into().strerr()?))
+            }
+            size => (8, size as u64),
+        };
+
+        // a size past the end of the file would seek nowhere or allocate whatever it claims
+        if size < header_len || size > len - pos {
+            return Err("broken MP4 atom").strerr();
+        }
+
+        if &header[4..8] == b"moov" {
+            let mut moov = vec![0; (size - header_len) as usize];
+            file.read_exact(&mut moov).strerr()?;
+            return Ok(Some(moov));
+        }
+
+        pos += size;
+    }
+}
+
+/// A QuickTime chapter track (`tref/chap`), as iTunes and Audible write, or else
+/// a Nero `moov/udta/chpl` atom.
+fn from_mp4(path: &Path) -> Result<Vec<Chapter>, String> {
+    let mut file = File::open(path).strerr()?;
+    let Some(moov) = read_moov(&mut file)? else {
+        return Ok(Vec::new());
+    };
+
+    match from_chapter_track(&mut file, &moov)? {
+        chapters if !chapters.is_empty() => Ok(chapters),
+        _ => Ok(find(&moov, &[b"udta", b"chpl"]).map_or_else(Vec::new, from_chpl)),
+    }
+}
+
+fn from_chpl(chpl: &[u8]) -> Vec<Chapter> {
+    // version and flags, 4 reserved bytes from version 1 on, then the count
+    let skip = if chpl.first().is_some_and(|&version| version > 0) {
+        8
+    } else {
+        4
+    };
+    let Some((&count, mut rest)) = chpl.get(skip..).and_then(<[u8]>::split_first) else {
+        return Vec::new();
+    };
+    let mut chapters = Vec::new();
+
+    for _ in 0..count {
+        // start in 100 ns units, then a length-prefixed UTF-8 title
+        let Some(start) = be_u64(rest, 0) else {
+            break;
+        };
+        let Some(&title_len) = rest.get(8) else {
+            break;
+        };
+        let Some(title) = rest.get(9..9 + title_len as usize) else {
+            break;
+        };
+
+        chapters.push(Chapter {
+            start: start as f64 / 10_000_000.0,
+            title: String::from_utf8_lossy(title).into_owned(),
+        });
+        rest = &rest[9 + title_len as usize..];
+    }
+
+    chapters
+}
+
+/// The text samples of the track another track points to with `tref/chap`.
+fn from_chapter_track(file: &mut File, moov: &[u8]) -> Result<Vec<Chapter>, String> {
+    let traks: Vec<_> = atoms(moov)
+        .into_iter()
+        .filter(|(kind, _)| kind == b"trak")
+        .map(|(_, trak)| trak)
+        .collect();
+    let track_id = |trak: &[u8]| after_times(atom(trak, b"tkhd")?);
+
+    let Some(chap_id) = traks
+        .iter()
+        .find_map(|trak| be_u32(find(trak, &[b"tref", b"chap"])?, 0))
+    else {
+        return Ok(Vec::new());
+    };
+    let Some(trak) = traks.iter().find(|&&trak| track_id(trak) == Some(chap_id)) else {
+        return Ok(Vec::new());
+    };
+    let Some(samples) = text_samples(trak) else {
+        return Ok(Vec::new());
+    };
+
+    let len = file.metadata().strerr()?.len();
+    let mut chapters = Vec::new();
+
+    for (start, offset, size) in samples {
+        // a sample is a 16-bit length and the title, sometimes followed by styling
+        if size < 2 || offset.saturating_add(size as u64) > len || size > 0x10000 {
+            continue;
+        }
+
+        let mut sample = vec![0; size as usize];
+        file.seek(SeekFrom::Start(offset)).strerr()?;
+        file.read_exact(&mut sample).strerr()?;
+
+        let text_len = u16::from_be_bytes([sample[0], sample[1]]) as usize;
+        let text = sample.get(2..2 + text_len).unwrap_or(&sample[2..]);
+
+        chapters.push(Chapter {
+            start,
+            title: decode_text(text),
+        });
+    }
+
+    Ok(chapters)
+}
+
+/// Start in seconds, file offset and size of each sample of `trak`.
+fn text_samples(trak: &[u8]) -> Option<Vec<(f64, u64, u32)>> {
+    let mdia = atom(trak, b"mdia")?;
+    let timescale = after_times(atom(mdia, b"mdhd")?)?;
+    let stbl = find(mdia, &[b"minf", b"stbl"])?;
+
+    // counts are never trusted further than the entries actually present
+    let entries = |kind: &[u8; 4], skip: usize, width: usize| {
+        atom(stbl, kind).map(|body| body.get(skip..).unwrap_or_default().chunks_exact(width))
+    };
+
+    let mut durations = Vec::new();
+    for entry in entries(b"stts", 8, 8)? {
+        let (count, delta) = (be_u32(entry, 0)?, be_u32(entry, 4)?);
+        durations.extend(std::iter::repeat_n(delta, count.min(0x10000) as usize));
+    }
+
+    let stsz = atom(stbl, b"stsz")?;
+    let sizes: Vec<u32> = match be_u32(stsz, 4)? {
+        0 => entries(b"stsz", 12, 4)?
+            .filter_map(|entry| be_u32(entry, 0))
+            .collect(),
+        size => vec![size; (be_u32(stsz, 8)? as usize).min(durations.len())],
+    };
+
+    let chunks: Vec<u64> = match entries(b"stco", 8, 4) {
+        Some(entries) => entries
+            .filter_map(|entry| be_u32(entry, 0).map(u64::from))
+            .collect(),
+        None => entries(b"co64", 8, 8)?
+            .filter_map(|entry| be_u64(entry, 0))
+            .collect(),
+    };
+    let runs: Vec<(u32, u32)> = entries(b"stsc", 8, 12)?
+        .filter_map(|entry| Some((be_u32(entry, 0)?, be_u32(entry, 4)?)))
+        .collect();
+
+    let mut samples = Vec::new();
+    let mut sizes = sizes.into_iter();
+    let mut time = 0u64;
+
+    'chunks: for (index, &offset) in chunks.iter().enumerate() {
+        let chunk = index as u32 + 1;
+        let per_chunk = runs
+            .iter()
+            .take_while(|&&(first, _)| first <= chunk)
+            .last()
+            .map_or(0, |&(_, per_chunk)| per_chunk);
+        let mut offset = offset;
+
+        for _ in 0..per_chunk {
+            let (Some(size), Some(&duration)) = (sizes.next(), durations.get(samples.len())) else {
+                break 'chunks;
+            };
+
+            samples.push((time as f64 / timescale.max(1) as f64, offset, size));
+            offset += size as u64;
+            time += duration as u64;
+        }
+    }
+
+    Some(samples)
+}
+
+/// UTF-8, or UTF-16 with a byte order mark.
+fn decode_text(bytes: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
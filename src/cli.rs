@@ -0,0 +1,146 @@
+use crate::queue::Repeat;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// An optimized MP3 player with smooth/dynamic speed controls.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub play: Play,
+}
+
+#[derive(Args)]
+pub struct Play {
+    /// Files or folders to play, folders are searched recursively and `-` reads
+    /// a path per line from stdin. Defaults to `library` in the config file
+    pub paths: Vec<PathBuf>,
+    /// Play in order instead of shuffling
+    #[arg(long)]
+    pub no_shuffle: bool,
+    /// Shuffle with a fixed seed, to get the same order again
+    #[arg(long, conflicts_with = "no_shuffle")]
+    pub seed: Option<u64>,
+    /// Volume to start at, in percent
+    #[arg(long, value_parser = parse_volume)]
+    pub volume: Option<f32>,
+    /// Speed to start at, e.g. 1.25
+    #[arg(long, value_parser = parse_speed)]
+    pub speed: Option<f32>,
+    /// Position to start the first track at, in seconds or [h:]mm:ss
+    #[arg(long, value_parser = parse_clock)]
+    pub start_at: Option<f64>,
+    /// Repeat mode, overriding the config file
+    #[arg(long, value_enum)]
+    pub repeat: Option<Repeat>,
+    #[command(flatten)]
+    pub filters: Filters,
+    /// Ignore the saved session and start over
+    #[arg(long)]
+    pub fresh: bool,
+}
+
+/// Which files are collected from folders.
+#[derive(Args)]
+pub struct Filters {
+    /// File extensions to collect from folders, comma separated and case-insensitive
+    #[arg(long, value_delimiter = ',', default_value = "mp3")]
+    pub extensions: Vec<String>,
+    /// Only collect files matching this glob (`*`, `**`, `?`); a pattern with a
+    /// `/` matches the path within the folder, otherwise the name. Can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Skip files and folders matching this glob. Can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Also collect hidden files and folders (names starting with `.`)
+    #[arg(long)]
+    pub hidden: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Measure the loudness (EBU R128) of every file ahead of time
+    Scan {
+        /// Files or folders to measure, `-` reads a path per line from stdin
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Also write the results as REPLAYGAIN_TRACK_GAIN/PEAK ID3 tags
+        #[arg(long)]
+        write_tags: bool,
+        #[command(flatten)]
+        filters: Filters,
+    },
+    /// Show stream and tag information of a file
+    Info { file: PathBuf },
+    /// Play a file through the effect chain into a WAV file, as fast as possible
+    Render {
+        file: PathBuf,
+        /// WAV file to write (32-bit float)
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, value_parser = parse_speed, default_value = "1")]
+        speed: f32,
+        /// Volume in percent
+        #[arg(long, value_parser = parse_volume, default_value = "100")]
+        volume: f32,
+    },
+    /// List tracks with a remembered position
+    Progress,
+}
+
+fn parse_speed(arg: &str) -> Result<f32, String> {
+    arg.parse::<f32>()
+        .ok()
+        .filter(|speed| speed.is_finite() && *speed > 0.0)
+        .ok_or_else(|| "expected a positive number like 1.25".to_string())
+}
+
+/// Percent to linear gain.
+fn parse_volume(arg: &str) -> Result<f32, String> {
+    arg.trim_end_matches('%')
+        .parse::<f32>()
+        .ok()
+        .filter(|volume| (0.0..=100.0).contains(volume))
+        .map(|volume| volume / 100.0)
+        .ok_or_else(|| "expected a percentage from 0 to 100".to_string())
+}
+
+/// Seconds, `mm:ss` or `h:mm:ss`.
+fn parse_clock(arg: &str) -> Result<f64, String> {
+    arg.split(':')
+        .try_fold(0.0, |secs, part| {
+            part.parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .map(|value| secs * 60.0 + value)
+        })
+        .filter(|_| arg.split(':').count() <= 3)
+        .ok_or_else(|| "expected seconds, mm:ss or h:mm:ss".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_accepts_seconds_and_minutes() {
+        assert_eq!(parse_clock("90"), Ok(90.0));
+        assert_eq!(parse_clock("1.5"), Ok(1.5));
+        assert_eq!(parse_clock("01:30"), Ok(90.0));
+        assert_eq!(parse_clock("1:02:03"), Ok(3723.0));
+    }
+
+    #[test]
+    fn clock_rejects_garbage() {
+        assert!(parse_clock("").is_err());
+        assert!(parse_clock("1:2:3:4").is_err());
+        assert!(parse_clock("-5").is_err());
+        assert!(parse_clock("1:-5").is_err());
+        assert!(parse_clock("inf").is_err());
+        assert!(parse_clock("NaN").is_err());
+        assert!(parse_clock("1::2").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    bookmarks, dirs, dynamics::Compressor, queue::Repeat, speed::SpeedMap, strerr::Strerr,
+    volume::VolumeLaw,
+};
+use sdl2::{keyboard::Keycode, pixels::Color};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Next,
+    Previous,
+    NextChapter,
+    PreviousChapter,
+    Equalizer,
+    EqPreset,
+    Chain,
+    GainMode,
+    Night,
+    Limiter,
+    VolumeLaw,
+    DbReadout,
+    Crossfeed,
+    Karaoke,
+    Narrow,
+    Widen,
+    LoopA,
+    LoopB,
+    ClearLoop,
+    Reverse,
+    SpeedDown,
+    SpeedUp,
+    VolumeDown,
+    VolumeUp,
+    Reset,
+    SpeedCurve,
+    SpeedSnap,
+    Repeat,
+    Shuffle,
+    Finished,
+    SaveQueue,
+    #[serde(rename = "speed_preset_1")]
+    SpeedPreset1,
+    #[serde(rename = "speed_preset_2")]
+    SpeedPreset2,
+    #[serde(rename = "speed_preset_3")]
+    SpeedPreset3,
+    #[serde(rename = "speed_preset_4")]
+    SpeedPreset4,
+    #[serde(rename = "speed_preset_5")]
+    SpeedPreset5,
+    #[serde(rename = "speed_preset_6")]
+    SpeedPreset6,
+}
+
+const DEFAULT_KEYS: &[(Action, &[Keycode])] = &[
+    (Action::Next, &[Keycode::SPACE]),
+    (Action::Previous, &[Keycode::BACKSPACE]),
+    (Action::NextChapter, &[Keycode::PAGEDOWN]),
+    (Action::PreviousChapter, &[Keycode::PAGEUP]),
+    (Action::Equalizer, &[Keycode::E]),
+    (Action::EqPreset, &[Keycode::P]),
+    (Action::Chain, &[Keycode::TAB]),
+    (Action::GainMode, &[Keycode::G]),
+    (Action::Night, &[Keycode::N]),
+    (Action::Limiter, &[Keycode::L]),
+    (Action::VolumeLaw, &[Keycode::V]),
+    (Action::DbReadout, &[Keycode::D]),
+    (Action::Crossfeed, &[Keycode::X]),
+    (Action::Karaoke, &[Keycode::K]),
+    (Action::Narrow, &[Keycode::LEFTBRACKET]),
+    (Action::Widen, &[Keycode::RIGHTBRACKET]),
+    (Action::LoopA, &[Keycode::A]),
+    (Action::LoopB, &[Keycode::B]),
+    (Action::ClearLoop, &[Keycode::C]),
+    (Action::Reverse, &[Keycode::R]),
+    (Action::SpeedDown, &[Keycode::LEFT]),
+    (Action::SpeedUp, &[Keycode::RIGHT]),
+    (
+        Action::VolumeDown,
+        &[Keycode::DOWN, Keycode::MINUS, Keycode::KP_MINUS],
+    ),
+    (
+        Action::VolumeUp,
+        &[
+            Keycode::UP,
+            Keycode::EQUALS,
+            Keycode::PLUS,
+            Keycode::KP_PLUS,
+        ],
+    ),
+    (Action::Reset, &[Keycode::NUM_0]),
+    (Action::SpeedCurve, &[Keycode::M]),
+    (Action::SpeedSnap, &[Keycode::S]),
+    (Action::Repeat, &[Keycode::T]),
+    (Action::Shuffle, &[Keycode::H]),
+    (Action::Finished, &[Keycode::F]),
+    (Action::SaveQueue, &[Keycode::W]),
+    (Action::SpeedPreset1, &[Keycode::NUM_1]),
+    (Action::SpeedPreset2, &[Keycode::NUM_2]),
+    (Action::SpeedPreset3, &[Keycode::NUM_3]),
+    (Action::SpeedPreset4, &[Keycode::NUM_4]),
+    (Action::SpeedPreset5, &[Keycode::NUM_5]),
+    (Action::SpeedPreset6, &[Keycode::NUM_6]),
+];
+
+/// Colours as `"#RRGGBB"` strings.
+mod hex {
+    use sdl2::pixels::Color;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S>(color: &Color, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Color, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        let rgb = text
+            .strip_prefix('#')
+            .filter(|digits| digits.len() == 6 && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| {
+                D::Error::custom(format!(
+                    "expected a colour like \"#RRGGBB\", found \"{text}\""
+                ))
+            })?;
+
+        Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    #[serde(with = "hex")]
+    pub speed: Color,
+    #[serde(with = "hex")]
+    pub volume: Color,
+    #[serde(with = "hex")]
+    pub played: Color,
+    #[serde(with = "hex")]
+    pub unplayed: Color,
+    #[serde(with = "hex")]
+    pub peak: Color,
+    #[serde(with = "hex")]
+    pub rms: Color,
+    #[serde(with = "hex")]
+    pub clip: Color,
+    #[serde(with = "hex")]
+    pub clip_off: Color,
+    #[serde(with = "hex")]
+    pub eq: Color,
+    #[serde(rename = "loop", with = "hex")]
+    pub looping: Color,
+    #[serde(with = "hex")]
+    pub chapter: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            speed: Color::RGB(0x00, 0x31, 0x61),
+            volume: Color::RGB(0x00, 0x6A, 0x67),
+            played: Color::RGB(0x90, 0x90, 0x90),
+            unplayed: Color::RGB(0x48, 0x48, 0x48),
+            peak: Color::RGB(0x2E, 0x7D, 0x32),
+            rms: Color::RGB(0x66, 0xBB, 0x6A),
+            clip: Color::RGB(0xFF, 0x17, 0x44),
+            clip_off: Color::RGB(0x40, 0x00, 0x00),
+            eq: Color::RGB(0x4A, 0x14, 0x8C),
+            looping: Color::RGB(0xFF, 0xC1, 0x07),
+            chapter: Color::RGB(0x4F, 0xC3, 0xF7),
+        }
+    }
+}
+
+/// Which tracks remember their position, like audiobooks and podcasts.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BookmarkConfig {
+    /// Tracks at least this many seconds long remember their position.
+    pub min_duration: f64,
+    /// Tracks under these folders always remember their position.
+    pub folders: Vec<PathBuf>,
+}
+
+impl Default for BookmarkConfig {
+    fn default() -> Self {
+        Self {
+            min_duration: 20.0 * 60.0,
+            folders: Vec::new(),
+        }
+    }
+}
+
+impl BookmarkConfig {
+    pub fn applies(&self, path: &Path, duration: f64) -> bool {
+        duration >= self.min_duration || {
+            let path = bookmarks::canonical(path);
+            self.folders
+                .iter()
+                .any(|folder| path.starts_with(bookmarks::canonical(folder)))
+        }
+    }
+}
+
+/// The night mode compressor.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightConfig {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for NightConfig {
+    fn default() -> Self {
+        let compressor = Compressor::new();
+
+        Self {
+            threshold_db: compressor.threshold_db,
+            ratio: compressor.ratio,
+            attack_ms: compressor.attack_ms,
+            release_ms: compressor.release_ms,
+            makeup_db: compressor.makeup_db,
+        }
+    }
+}
+
+impl NightConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !self.threshold_db.is_finite() || !self.makeup_db.is_finite() {
+            return Err("night threshold_db and makeup_db must be finite".to_string());
+        }
+
+        if !self.ratio.is_finite() || self.ratio < 1.0 {
+            return Err(format!(
+                "night ratio must be at least 1, found {}",
+                self.ratio
+            ));
+        }
+
+        for (name, ms) in [
+            ("attack_ms", self.attack_ms),
+            ("release_ms", self.release_ms),
+        ] {
+            if !ms.is_finite() || ms <= 0.0 {
+                return Err(format!("night {name} must be positive, found {ms}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub const fn apply(&self, compressor: &mut Compressor) {
+        compressor.threshold_db = self.threshold_db;
+        compressor.ratio = self.ratio;
+        compressor.attack_ms = self.attack_ms;
+        compressor.release_ms = self.release_ms;
+        compressor.makeup_db = self.makeup_db;
+    }
+}
+
+/// Settings kept in `config.toml` in the config directory. Missing fields take
+/// their defaults, and the file is rewritten with the current state on exit.
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Speed when the player was last closed.
+    pub speed: f32,
+    /// Linear gain when the player was last closed.
+    pub volume: f32,
+    pub volume_law: VolumeLaw,
+    pub repeat: Repeat,
+    pub shuffle: bool,
+    /// Seconds the speed, volume and progress bars take to animate.
+    pub transition: f32,
+    /// Folders played when none is given on the command line.
+    pub library: Vec<PathBuf>,
+    #[serde(rename = "speed_bar")]
+    pub speed_map: SpeedMap,
+    pub theme: Theme,
+    pub bookmarks: BookmarkConfig,
+    pub night: NightConfig,
+    /// Key names as SDL spells them (e.g. `"Space"`, `"Left"`, `"Keypad +"`).
+    /// Only the user's own bindings, actions left out keep their default keys.
+    pub keys: BTreeMap<Action, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            volume: VolumeLaw::origin_gain(),
+            volume_law: VolumeLaw::Decibel,
+            repeat: Repeat::Off,
+            shuffle: true,
+            transition: 0.3,
+            library: Vec::new(),
+            speed_map: SpeedMap::new(),
+            theme: Theme::default(),
+            bookmarks: BookmarkConfig::default(),
+            night: NightConfig::default(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Reads the config file, falling back to defaults if there is none.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let Some(config) = dirs::read_toml::<Self>(&path)? else {
+            return Ok(Self::default());
+        };
+
+        config
+            .validate()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.speed.is_nan() || self.speed <= 0.0 {
+            return Err(format!("speed must be positive, found {}", self.speed));
+        }
+
+        if !(0.0..=1.0).contains(&self.volume) {
+            return Err(format!("volume must be in [0, 1], found {}", self.volume));
+        }
+
+        if self.transition.is_nan() || self.transition < 0.0 {
+            return Err(format!(
+                "transition must not be negative, found {}",
+                self.transition
+            ));
+        }
+
+        self.speed_map.validate()?;
+        self.night.validate()?;
+        self.bindings().map(|_| ())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no config directory")?;
+        let text = toml::to_string_pretty(self).strerr()?;
+
+        dirs::write_atomic(&path, text)
+    }
+
+    /// The user's bindings, then the default keys of the actions they left out.
+    /// Keys the user bound themselves are taken away from the actions they default to.
+    pub fn bindings(&self) -> Result<HashMap<Keycode, Action>, String> {
+        let mut bindings = HashMap::new();
+
+        for (&action, names) in self.keys.iter() {
+            for name in names {
+                let keycode = Keycode::from_name(name)
+                    .ok_or_else(|| format!("unknown key \"{name}\" in [keys]"))?;
+                if bindings
+                    .insert(keycode, action)
+                    .is_some_and(|other| other != action)
+                {
+                    return Err(format!(
+                        "key \"{name}\" is bound to more than one action in [keys]"
+                    ));
+                }
+            }
+        }
+
+        for &(action, keys) in DEFAULT_KEYS {
+            if self.keys.contains_key(&action) {
+                continue;
+            }
+
+            for &key in keys {
+                bindings.entry(key).or_insert(action);
+            }
+        }
+
+        Ok(bindings)
+    }
+}
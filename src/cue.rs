@@ -0,0 +1,247 @@
+use crate::{queue::Track, strerr::Strerr};
+use std::path::{Path, PathBuf};
+
+/// `INDEX` times are `mm:ss:ff`, in CD frames.
+const FRAMES_PER_SEC: f64 = 75.0;
+
+/// What the decoder can play, for finding the file a sheet's `.wav` became.
+const AUDIO_EXTENSIONS: [&str; 11] = [
+    "aac", "flac", "m4a", "mka", "mkv", "mp3", "mp4", "oga", "ogg", "wav", "webm",
+];
+
+pub fn is_cue(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// One `TRACK` of the sheet as it is being read.
+struct Entry {
+    file: PathBuf,
+    number: String,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<f64>,
+}
+
+impl Entry {
+    fn into_track(self) -> Option<Track> {
+        let title = match (self.performer, self.title) {
+            (Some(performer), Some(title)) => format!("{performer} - {title}"),
+            (None, Some(title)) => title,
+            (_, None) => format!("Track {}", self.number),
+        };
+
+        Some(Track {
+            title: Some(title),
+            start: Some(self.start?),
+            ..Track::new(self.file)
+        })
+    }
+}
+
+/// Splits the files of a cue sheet into one track per `TRACK`, starting at its
+/// `INDEX 01` and ending where the next track in the same file starts.
+pub fn load(path: &Path) -> Result<Vec<Track>, String> {
+    let bytes = std::fs::read(path).strerr()?;
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{FEFF}');
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut tracks = Vec::new();
+    let mut file = None;
+    let mut entry: Option<Entry> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                tracks.extend(entry.take().and_then(Entry::into_track));
+                file = Some(resolve(base, &file_name(rest)));
+            }
+            "TRACK" => {
+                tracks.extend(entry.take().and_then(Entry::into_track));
+                entry = file.clone().map(|file| Entry {
+                    file,
+                    number: rest
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    title: None,
+                    performer: None,
+                    start: None,
+                });
+            }
+            "TITLE" => {
+                if let Some(entry) = entry.as_mut() {
+                    entry.title = Some(unquote(rest));
+                }
+            }
+            "PERFORMER" => {
+                if let Some(entry) = entry.as_mut() {
+                    entry.performer = Some(unquote(rest));
+                }
+            }
+            "INDEX" => {
+                if let Some(entry) = entry.as_mut()
+                    && let Some((number, time)) = rest.split_once(char::is_whitespace)
+                    && number.parse() == Ok(1)
+                {
+                    entry.start = Some(
+                        parse_time(time.trim())
+                            .ok_or_else(|| format!("invalid INDEX time \"{}\"", time.trim()))?,
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+
+    tracks.extend(entry.and_then(Entry::into_track));
+
+    // each track ends where the next one in the same file starts, the last one at the end
+    let starts: Vec<_> = tracks
+        .iter()
+        .map(|track| (track.path.clone(), track.start))
+        .skip(1)
+        .collect();
+
+    for (track, (path, start)) in tracks.iter_mut().zip(starts) {
+        if track.path == path {
+            track.end = start;
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// `"name with spaces.flac" WAVE` or `name.flac WAVE`.
+fn file_name(rest: &str) -> String {
+    match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default().to_string(),
+        None => rest
+            .rsplit_once(char::is_whitespace)
+            .map_or(rest, |(name, _)| name)
+            .to_string(),
+    }
+}
+
+fn unquote(text: &str) -> String {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+        .to_string()
+}
+
+/// `mm:ss:ff` to seconds.
+fn parse_time(time: &str) -> Option<f64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+
+    (parts.next().is_none() && seconds < 60 && (frames as f64) < FRAMES_PER_SEC)
+        .then(|| minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SEC)
+}
+
+/// Sheets often still name the `.wav` that was ripped before it was compressed,
+/// so a missing file falls back to one with the same name and another extension.
+fn resolve(base: &Path, name: &str) -> PathBuf {
+    let path = base.join(name);
+
+    if path.exists() {
+        return path;
+    }
+
+    let dir = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+    let candidates = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| base.join(entry.file_name()));
+
+    best_match(&path, candidates).unwrap_or(path)
+}
+
+/// The audio file among `candidates` with the stem of `path`, preferring its
+/// extension in any case, then the first by name. Logs, covers and rip reports
+/// sharing the stem are passed over.
+fn best_match(path: &Path, candidates: impl Iterator<Item = PathBuf>) -> Option<PathBuf> {
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+    };
+    let wanted = extension(path);
+
+    candidates
+        .filter(|candidate| candidate.file_stem() == path.file_stem())
+        .filter(|candidate| {
+            extension(candidate).is_some_and(|extension| AUDIO_EXTENSIONS.contains(&&*extension))
+        })
+        .min_by_key(|candidate| (extension(candidate) != wanted, candidate.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_are_minutes_seconds_and_frames() {
+        assert_eq!(parse_time("00:00:00"), Some(0.0));
+        assert_eq!(parse_time("03:25:15"), Some(205.2));
+        assert_eq!(parse_time("75:00:74"), Some(4500.0 + 74.0 / 75.0));
+    }
+
+    #[test]
+    fn invalid_times_are_rejected() {
+        assert_eq!(parse_time("01:60:00"), None);
+        assert_eq!(parse_time("01:00:75"), None);
+        assert_eq!(parse_time("01:00"), None);
+        assert_eq!(parse_time("01:00:00:00"), None);
+        assert_eq!(parse_time("aa:00:00"), None);
+    }
+
+    #[test]
+    fn missing_files_fall_back_to_audio_with_the_same_name() {
+        let candidates = |names: &[&str]| {
+            let paths: Vec<_> = names.iter().map(PathBuf::from).collect();
+            paths.into_iter()
+        };
+
+        assert_eq!(
+            best_match(
+                Path::new("Album.wav"),
+                candidates(&["Album.accurip", "Album.jpg", "Album.log", "Album.flac"])
+            ),
+            Some(PathBuf::from("Album.flac"))
+        );
+        assert_eq!(
+            best_match(
+                Path::new("Album.WAV"),
+                candidates(&["Album.flac", "Album.wav", "Other.mp3"])
+            ),
+            Some(PathBuf::from("Album.wav"))
+        );
+        assert_eq!(
+            best_match(
+                Path::new("Album.wav"),
+                candidates(&["Album.cue", "Album.log"])
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn file_names_may_be_quoted() {
+        assert_eq!(file_name(r#""Album Name.flac" WAVE"#), "Album Name.flac");
+        assert_eq!(file_name("album.flac WAVE"), "album.flac");
+        assert_eq!(file_name("album.flac"), "album.flac");
+        assert_eq!(file_name(r#""unterminated.wav"#), "unterminated.wav");
+    }
+}
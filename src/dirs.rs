@@ -0,0 +1,60 @@
+use crate::strerr::Strerr;
+use serde::de::DeserializeOwned;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+const APP: &str = "mp3";
+
+fn xdg(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .map(|base| base.join(APP))
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg("XDG_CACHE_HOME", ".cache")
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    xdg("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    xdg("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    xdg("XDG_STATE_HOME", ".local/state")
+}
+
+/// Reads a TOML file, or `None` if there is none yet.
+pub fn read_toml<T>(path: &Path) -> Result<Option<T>, String>
+where
+    T: DeserializeOwned,
+{
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+
+    toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Writes next to `path` first and renames over it, so a failure never leaves
+/// a truncated file. Missing folders are created.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).strerr()?;
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".saving");
+    std::fs::write(&temp, contents).strerr()?;
+    std::fs::rename(&temp, path).strerr()
+}
@@ -0,0 +1,171 @@
+use crate::{
+    dynamics::{Compressor, Limiter},
+    eq::Equalizer,
+    playback::Sample,
+    stereo::{Crossfeed, StereoWidth, VocalRemover},
+};
+use std::any::Any;
+
+/// Converts a linear gain to dB. Silence comes out as a very low level rather
+/// than negative infinity.
+pub fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-9).log10()
+}
+
+pub fn to_amplitude(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// An effect working in place on interleaved frames.
+pub trait Processor: Any + Send {
+    fn name(&self) -> &'static str;
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize);
+
+    /// Forgets any signal history, e.g. when a new track starts.
+    fn reset(&mut self) {}
+}
+
+struct Slot {
+    processor: Box<dyn Processor>,
+    enabled: bool,
+    was_enabled: bool,
+}
+
+/// Ordered effects run by `Playback`, either between interpolation and volume
+/// or as the output stage after volume.
+/// Toggling an effect crossfades between its dry and wet signal over one block.
+pub struct Chain {
+    slots: Vec<Slot>,
+    dry: Vec<Sample>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            dry: Vec::new(),
+        }
+    }
+
+    /// The effects every session starts with, in processing order.
+    pub fn standard() -> Self {
+        let mut chain = Self::new();
+        chain.push(Equalizer::new(), true);
+        chain.push(Compressor::new(), false);
+        chain.push(Crossfeed::new(), false);
+        chain.push(VocalRemover::new(), false);
+        chain.push(StereoWidth::new(), true);
+        chain
+    }
+
+    /// The final stages, run after volume.
+    pub fn output() -> Self {
+        let mut chain = Self::new();
+        chain.push(Limiter::new(), true);
+        chain
+    }
+
+    pub fn push<T>(&mut self, processor: T, enabled: bool)
+    where
+        T: Processor,
+    {
+        self.slots.push(Slot {
+            processor: Box::new(processor),
+            enabled,
+            was_enabled: enabled,
+        });
+    }
+
+    /// Names and enabled states, in processing order.
+    pub fn summary(&self) -> Vec<(&'static str, bool)> {
+        self.slots
+            .iter()
+            .map(|slot| (slot.processor.name(), slot.enabled))
+            .collect()
+    }
+
+    pub fn get_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: Processor,
+    {
+        self.slots.iter_mut().find_map(|slot| {
+            let any: &mut dyn Any = slot.processor.as_mut();
+            any.downcast_mut()
+        })
+    }
+
+    pub fn index_of<T>(&self) -> Option<usize>
+    where
+        T: Processor,
+    {
+        self.slots.iter().position(|slot| {
+            let any: &dyn Any = slot.processor.as_ref();
+            any.is::<T>()
+        })
+    }
+
+    pub fn is_enabled<T>(&self) -> bool
+    where
+        T: Processor,
+    {
+        self.index_of::<T>()
+            .is_some_and(|index| self.slots[index].enabled)
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            slot.enabled = !slot.enabled;
+        }
+    }
+
+    /// Moves the effect at `index` by `offset` slots, clamped to the chain.
+    pub fn shift(&mut self, index: usize, offset: i32) {
+        if index < self.slots.len() {
+            let to = (index as i32 + offset).clamp(0, self.slots.len() as i32 - 1) as usize;
+            let slot = self.slots.remove(index);
+            self.slots.insert(to, slot);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.processor.reset();
+        }
+    }
+
+    pub fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        let frames = block.len() / channels;
+
+        for slot in self.slots.iter_mut() {
+            match (slot.was_enabled, slot.enabled) {
+                (false, false) => continue,
+                (true, true) => slot.processor.process(block, sample_rate, channels),
+                (was_enabled, _) => {
+                    if !was_enabled {
+                        slot.processor.reset();
+                    }
+
+                    self.dry.clear();
+                    self.dry.extend_from_slice(block);
+                    slot.processor.process(block, sample_rate, channels);
+
+                    for (frame, (wet, dry)) in block
+                        .chunks_exact_mut(channels)
+                        .zip(self.dry.chunks_exact(channels))
+                        .enumerate()
+                    {
+                        let t = frame as f32 / frames as f32;
+                        let t = if was_enabled { 1.0 - t } else { t };
+
+                        for (wet, &dry) in wet.iter_mut().zip(dry) {
+                            *wet = t * *wet + (1.0 - t) * dry;
+                        }
+                    }
+                }
+            }
+
+            slot.was_enabled = slot.enabled;
+        }
+    }
+}
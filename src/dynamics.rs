@@ -0,0 +1,159 @@
+use crate::{
+    dsp::{Processor, to_amplitude, to_db},
+    playback::Sample,
+};
+use std::collections::VecDeque;
+
+/// One-pole smoothing coefficient reaching ~63% after `ms`.
+fn coefficient(ms: f32, sample_rate: u32) -> f32 {
+    1.0 - (-1000.0 / (ms * sample_rate as f32)).exp()
+}
+
+/// Stereo-linked feed-forward compressor ("night mode").
+pub struct Compressor {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub const fn new() -> Self {
+        Self {
+            threshold_db: -30.0,
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+            makeup_db: 10.0,
+            reduction_db: 0.0,
+        }
+    }
+}
+
+impl Processor for Compressor {
+    fn name(&self) -> &'static str {
+        "compressor"
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        let attack = coefficient(self.attack_ms, sample_rate);
+        let release = coefficient(self.release_ms, sample_rate);
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+
+        for frame in block.chunks_exact_mut(channels) {
+            let level = frame
+                .iter()
+                .fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+            let target = (to_db(level) - self.threshold_db).max(0.0) * slope;
+            let coefficient = if target > self.reduction_db {
+                attack
+            } else {
+                release
+            };
+
+            self.reduction_db += (target - self.reduction_db) * coefficient;
+
+            let gain = to_amplitude(self.makeup_db - self.reduction_db);
+
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// Lookahead brickwall limiter. Output never exceeds `ceiling`.
+pub struct Limiter {
+    pub ceiling: Sample,
+    pub lookahead_ms: f32,
+    pub release_ms: f32,
+    delay: VecDeque<Sample>,
+    /// Required gains of the frames in `delay`, kept increasing from front to back
+    /// so the front is always the minimum over the lookahead window.
+    minimum: VecDeque<(u64, f32)>,
+    frame: u64,
+    gain: f32,
+}
+
+impl Limiter {
+    pub const fn new() -> Self {
+        Self {
+            ceiling: 1.0,
+            lookahead_ms: 5.0,
+            release_ms: 50.0,
+            delay: VecDeque::new(),
+            minimum: VecDeque::new(),
+            frame: 0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Processor for Limiter {
+    fn name(&self) -> &'static str {
+        "limiter"
+    }
+
+    fn reset(&mut self) {
+        self.delay.clear();
+        self.minimum.clear();
+        self.gain = 1.0;
+    }
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        let lookahead = ((self.lookahead_ms * sample_rate as f32 / 1000.0) as u64).max(1);
+        // reaches 99% of a reduction within the lookahead window
+        let attack = 1.0 - 0.01_f32.powf(1.0 / lookahead as f32);
+        let release = coefficient(self.release_ms, sample_rate);
+
+        if self.delay.is_empty() {
+            self.delay
+                .extend(std::iter::repeat_n(0.0, lookahead as usize * channels));
+        }
+
+        for frame in block.chunks_exact_mut(channels) {
+            let peak = frame
+                .iter()
+                .fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+            let required = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+
+            while self
+                .minimum
+                .back()
+                .is_some_and(|&(_, gain)| gain >= required)
+            {
+                self.minimum.pop_back();
+            }
+            self.minimum.push_back((self.frame, required));
+
+            while self
+                .minimum
+                .front()
+                .is_some_and(|&(frame, _)| frame + lookahead < self.frame)
+            {
+                self.minimum.pop_front();
+            }
+
+            let target = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+            let coefficient = if target < self.gain { attack } else { release };
+            self.gain += (target - self.gain) * coefficient;
+            self.frame += 1;
+
+            for sample in frame.iter_mut() {
+                self.delay.push_back(*sample);
+                let delayed = self.delay.pop_front().unwrap_or_default();
+                *sample = (delayed * self.gain).clamp(-self.ceiling, self.ceiling);
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    playback::{Playback, Sample},
+    playback::{Carry, Playback},
     strerr::Strerr,
 };
 use sdl2::{
@@ -67,6 +67,11 @@ impl Engine {
         self.canvas.fill_rect(rect)
     }
 
+    pub fn draw_rects(&mut self, rects: &[Rect], color: Color) -> Result<(), String> {
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(rects)
+    }
+
     pub fn draw_surface(&mut self, surface: Surface, (x, y): (i32, i32)) -> Result<(), String> {
         let texture = surface.as_texture(&self.texture_creator).strerr()?;
         self.canvas.copy(
@@ -79,12 +84,14 @@ impl Engine {
     pub fn load_device<P>(
         &self,
         path: P,
-        buffer: Option<Box<[Sample]>>,
+        span: (f64, Option<f64>),
+        carry: Option<Carry>,
     ) -> Result<AudioDevice<Playback>, String>
     where
         P: AsRef<std::path::Path>,
     {
-        let playback = Playback::new(path, buffer)?;
+        let mut playback = Playback::new(path, carry)?;
+        playback.set_bounds(span)?;
         let sample_rate = playback.sample_rate as i32;
         let channels = playback.channels as u8;
         let desired_spec = AudioSpecDesired {
@@ -0,0 +1,268 @@
+use crate::{dsp::Processor, playback::Sample, smooth::Smooth};
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy)]
+pub enum Shape {
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+/// Normalized biquad coefficients (`a0 == 1`), from the RBJ audio EQ cookbook.
+#[derive(Clone, Copy)]
+pub struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    pub const fn raw(b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self { b0, b1, b2, a1, a2 }
+    }
+
+    pub fn new(shape: Shape, freq: f32, q: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let freq = freq.min(sample_rate as f32 * 0.45);
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            Shape::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            Shape::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                )
+            }
+            Shape::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                )
+            }
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Second-order Butterworth low-pass when `q` is `FRAC_1_SQRT_2`.
+    pub fn lowpass(freq: f32, q: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * freq.min(sample_rate as f32 * 0.45) / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 - cos) / 2.0 / a0,
+            b1: (1.0 - cos) / a0,
+            b2: (1.0 - cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// Second-order Butterworth high-pass when `q` is `FRAC_1_SQRT_2`.
+    pub fn highpass(freq: f32, q: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * freq.min(sample_rate as f32 * 0.45) / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        let lerp = |from: f32, to: f32| t * to + (1.0 - t) * from;
+
+        Self {
+            b0: lerp(self.b0, to.b0),
+            b1: lerp(self.b1, to.b1),
+            b2: lerp(self.b2, to.b2),
+            a1: lerp(self.a1, to.a1),
+            a2: lerp(self.a2, to.a2),
+        }
+    }
+}
+
+/// Transposed direct form II state of a single biquad on a single channel.
+#[derive(Clone, Copy, Default)]
+pub struct Biquad {
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    pub const fn process(&mut self, c: &Coefficients, x: Sample) -> Sample {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Preset {
+    Flat,
+    BassBoost,
+    Vocal,
+    Loudness,
+}
+
+impl Preset {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::BassBoost => "bass boost",
+            Self::Vocal => "vocal",
+            Self::Loudness => "loudness",
+        }
+    }
+
+    pub const fn gains(self) -> [f32; Equalizer::BANDS] {
+        match self {
+            Self::Flat => [0.0; Equalizer::BANDS],
+            Self::BassBoost => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Self::Vocal => [-2.0, -2.0, -1.0, 0.0, 2.0, 4.0, 4.0, 2.0, 0.0, -1.0],
+            Self::Loudness => [6.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 2.0, 4.0, 5.0],
+        }
+    }
+
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Flat => Self::BassBoost,
+            Self::BassBoost => Self::Vocal,
+            Self::Vocal => Self::Loudness,
+            Self::Loudness => Self::Flat,
+        }
+    }
+}
+
+/// 10-band graphic equalizer. The outermost bands are shelves.
+pub struct Equalizer {
+    gains: [Smooth; Self::BANDS],
+    states: Vec<[Biquad; Self::BANDS]>,
+    sample_rate: u32,
+}
+
+impl Equalizer {
+    pub const BANDS: usize = 10;
+    pub const MAX_GAIN_DB: f32 = 12.0;
+    pub const FREQUENCIES: [f32; Self::BANDS] = [
+        31.25, 62.5, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+    ];
+    const Q: f32 = std::f32::consts::SQRT_2;
+
+    pub fn new() -> Self {
+        Self {
+            gains: [const { Smooth::new(0.0) }; Self::BANDS],
+            states: Vec::new(),
+            sample_rate: 0,
+        }
+    }
+
+    pub fn set_gains(&mut self, gains: &[f32; Self::BANDS]) {
+        for (smooth, &gain) in self.gains.iter_mut().zip(gains) {
+            smooth.set(gain.clamp(-Self::MAX_GAIN_DB, Self::MAX_GAIN_DB));
+        }
+    }
+
+    const fn shape(band: usize) -> Shape {
+        match band {
+            0 => Shape::LowShelf,
+            b if b == Self::BANDS - 1 => Shape::HighShelf,
+            _ => Shape::Peaking,
+        }
+    }
+
+    fn is_flat(&self) -> bool {
+        self.gains
+            .iter()
+            .all(|gain| gain.interpolate(0.0) == 0.0 && gain.interpolate(1.0) == 0.0)
+    }
+}
+
+impl Processor for Equalizer {
+    fn name(&self) -> &'static str {
+        "equalizer"
+    }
+
+    fn reset(&mut self) {
+        self.states.fill([Biquad::default(); Self::BANDS]);
+    }
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        if self.sample_rate != sample_rate || self.states.len() != channels {
+            self.sample_rate = sample_rate;
+            self.states = vec![[Biquad::default(); Self::BANDS]; channels];
+        }
+
+        if self.is_flat() {
+            self.reset();
+        } else {
+            let coefficients = |t: f32| {
+                std::array::from_fn::<_, { Self::BANDS }, _>(|band| {
+                    Coefficients::new(
+                        Self::shape(band),
+                        Self::FREQUENCIES[band],
+                        Self::Q,
+                        self.gains[band].interpolate(t),
+                        sample_rate,
+                    )
+                })
+            };
+            let from = coefficients(0.0);
+            let to = coefficients(1.0);
+            let frames = block.len() / channels;
+
+            for (frame, samples) in block.chunks_exact_mut(channels).enumerate() {
+                let t = frame as f32 / frames as f32;
+                let current = std::array::from_fn::<_, { Self::BANDS }, _>(|band| {
+                    from[band].lerp(&to[band], t)
+                });
+
+                for (sample, state) in samples.iter_mut().zip(self.states.iter_mut()) {
+                    for (biquad, c) in state.iter_mut().zip(current.iter()) {
+                        *sample = biquad.process(c, *sample);
+                    }
+                }
+            }
+        }
+
+        for gain in self.gains.iter_mut() {
+            gain.stabilize();
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{dsp, loudness::Loudness};
+use symphonia::core::meta::{StandardTagKey, Tag, Value};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GainMode {
+    Off,
+    Track,
+    Album,
+}
+
+impl GainMode {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Track,
+            Self::Track => Self::Album,
+            Self::Album => Self::Off,
+        }
+    }
+}
+
+/// Loudness normalization info from ReplayGain or R128 tags. Gains are
+/// relative to the ReplayGain reference of -18 LUFS, peaks are linear.
+#[derive(Clone, Copy, Default)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub const REFERENCE_LUFS: f32 = -18.0;
+    /// R128 gains are relative to -23 LUFS.
+    const R128_OFFSET_DB: f32 = 5.0;
+
+    pub fn from_tags<'a, I>(tags: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Tag>,
+    {
+        let mut gain = Self::default();
+
+        for tag in tags {
+            let Value::String(value) = &tag.value else {
+                continue;
+            };
+
+            let key = tag.key.trim_start_matches("TXXX:").to_ascii_uppercase();
+            let slot =
+                match (tag.std_key, key.as_str()) {
+                    (Some(StandardTagKey::ReplayGainTrackGain), _)
+                    | (_, "REPLAYGAIN_TRACK_GAIN") => &mut gain.track_gain,
+                    (Some(StandardTagKey::ReplayGainTrackPeak), _)
+                    | (_, "REPLAYGAIN_TRACK_PEAK") => &mut gain.track_peak,
+                    (Some(StandardTagKey::ReplayGainAlbumGain), _)
+                    | (_, "REPLAYGAIN_ALBUM_GAIN") => &mut gain.album_gain,
+                    (Some(StandardTagKey::ReplayGainAlbumPeak), _)
+                    | (_, "REPLAYGAIN_ALBUM_PEAK") => &mut gain.album_peak,
+                    (_, "R128_TRACK_GAIN") => {
+                        gain.track_gain = parse_r128(value).or(gain.track_gain);
+                        continue;
+                    }
+                    (_, "R128_ALBUM_GAIN") => {
+                        gain.album_gain = parse_r128(value).or(gain.album_gain);
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+            *slot = parse_db(value).or(*slot);
+        }
+
+        gain
+    }
+
+    pub fn is_tagged(&self) -> bool {
+        self.track_gain.is_some() || self.album_gain.is_some()
+    }
+
+    /// Track gain and peak from a measurement, for files without tags.
+    pub fn from_loudness(loudness: &Loudness) -> Self {
+        Self {
+            track_gain: Some(Self::REFERENCE_LUFS - loudness.integrated),
+            track_peak: Some(loudness.true_peak),
+            ..Default::default()
+        }
+    }
+
+    /// Linear pre-gain for `mode`, lowered if needed so the stored peak stays below full scale.
+    pub fn factor(&self, mode: GainMode) -> f32 {
+        let (gain, peak) = match mode {
+            GainMode::Off => return 1.0,
+            GainMode::Track => (
+                self.track_gain.or(self.album_gain),
+                self.track_peak.or(self.album_peak),
+            ),
+            GainMode::Album => (
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            ),
+        };
+
+        gain.map_or(1.0, |gain| {
+            let factor = dsp::to_amplitude(gain);
+            peak.filter(|&peak| peak > 0.0)
+                .map_or(factor, |peak| factor.min(1.0 / peak))
+        })
+    }
+}
+
+/// Parses values such as `-6.48 dB` or `0.988553`.
+fn parse_db(value: &str) -> Option<f32> {
+    value
+        .split_whitespace()
+        .next()?
+        .trim_end_matches("dB")
+        .parse()
+        .ok()
+}
+
+/// Parses a Q7.8 fixed-point R128 gain.
+fn parse_r128(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<i16>()
+        .ok()
+        .map(|q| q as f32 / 256.0 + ReplayGain::R128_OFFSET_DB)
+}
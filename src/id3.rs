@@ -0,0 +1,272 @@
+use crate::strerr::Strerr;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+};
+
+const HEADER_LEN: usize = 10;
+const PADDING: usize = 256;
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, &byte| (acc << 7) | (byte & 0x7F) as usize)
+}
+
+fn to_syncsafe(value: usize) -> [u8; 4] {
+    std::array::from_fn(|i| ((value >> (7 * (3 - i))) & 0x7F) as u8)
+}
+
+/// Why the tag starting with `header` cannot be read, if it cannot.
+fn unsupported(header: &[u8]) -> Option<String> {
+    let (major, flags) = (header[3], header[5]);
+
+    if !matches!(major, 3 | 4) {
+        Some(format!("unsupported ID3v2.{major} tag"))
+    } else if flags & 0xC0 != 0 {
+        Some("unsynchronised or extended ID3v2 tags are not supported".into())
+    } else {
+        None
+    }
+}
+
+/// Whether the ID3v2 tag starting with `header` is one `Tag` can read.
+pub fn is_supported(header: &[u8]) -> bool {
+    header.len() >= HEADER_LEN && unsupported(header).is_none()
+}
+
+pub struct Frame {
+    pub id: [u8; 4],
+    pub flags: [u8; 2],
+    pub data: Vec<u8>,
+}
+
+/// An ID3v2.3/2.4 tag at the start of a file, without unsynchronisation or
+/// an extended header.
+pub struct Tag {
+    pub major: u8,
+    pub frames: Vec<Frame>,
+    /// Length of the tag in the file, including header and footer.
+    pub len: usize,
+}
+
+impl Tag {
+    pub fn parse(bytes: &[u8]) -> Result<Option<Self>, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..3] != b"ID3" {
+            return Ok(None);
+        }
+
+        let major = bytes[3];
+        let flags = bytes[5];
+        let size = syncsafe(&bytes[6..10]);
+        let footer = if flags & 0x10 != 0 { HEADER_LEN } else { 0 };
+
+        if let Some(e) = unsupported(bytes) {
+            return Err(e);
+        }
+
+        let body = bytes
+            .get(HEADER_LEN..HEADER_LEN + size)
+            .ok_or("truncated ID3v2 tag")?;
+
+        Ok(Some(Self {
+            major,
+            frames: parse_frames(major, body)?,
+            len: HEADER_LEN + size + footer,
+        }))
+    }
+
+    /// Reads only the tag at the start of `path`, not the audio after it.
+    pub fn read<P>(path: P) -> Result<Option<Self>, String>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::open(path).strerr()?;
+        let mut bytes = vec![0; HEADER_LEN];
+
+        if file.read_exact(&mut bytes).is_err() || &bytes[..3] != b"ID3" {
+            return Ok(None);
+        }
+
+        let size = syncsafe(&bytes[6..10]);
+        bytes.resize(HEADER_LEN + size, 0);
+        file.read_exact(&mut bytes[HEADER_LEN..]).strerr()?;
+
+        Self::parse(&bytes)
+    }
+
+    /// The frames, without header or padding.
+    fn encode_frames(&self) -> Vec<u8> {
+        self.frames
+            .iter()
+            .flat_map(|frame| {
+                let len = match self.major {
+                    4 => to_syncsafe(frame.data.len()),
+                    _ => (frame.data.len() as u32).to_be_bytes(),
+                };
+
+                frame
+                    .id
+                    .into_iter()
+                    .chain(len)
+                    .chain(frame.flags)
+                    .chain(frame.data.iter().copied())
+            })
+            .collect()
+    }
+
+    fn encode(frames: Vec<u8>, major: u8, padding: usize) -> Vec<u8> {
+        b"ID3"
+            .iter()
+            .copied()
+            .chain([major, 0, 0])
+            .chain(to_syncsafe(frames.len() + padding))
+            .chain(frames)
+            .chain(std::iter::repeat_n(0, padding))
+            .collect()
+    }
+}
+
+/// Frames one after another until the padding, as in a tag body or the
+/// embedded frames of `CHAP` and `CTOC`.
+pub fn parse_frames(major: u8, body: &[u8]) -> Result<Vec<Frame>, String> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+
+    while pos + HEADER_LEN <= body.len() && body[pos] != 0 {
+        let header = &body[pos..pos + HEADER_LEN];
+        let len = match major {
+            4 => syncsafe(&header[4..8]),
+            _ => u32::from_be_bytes(header[4..8].try_into().strerr()?) as usize,
+        };
+        let data = body
+            .get(pos + HEADER_LEN..pos + HEADER_LEN + len)
+            .ok_or("truncated ID3v2 frame")?;
+
+        frames.push(Frame {
+            id: header[..4].try_into().strerr()?,
+            flags: header[8..10].try_into().strerr()?,
+            data: data.to_vec(),
+        });
+
+        pos += HEADER_LEN + len;
+    }
+
+    Ok(frames)
+}
+
+/// Decodes an ID3v2 text field of the given encoding.
+pub fn decode_text(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        1 | 2 => {
+            let (big_endian, bytes) = match bytes {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == 2, bytes),
+            };
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| match big_endian {
+                    true => u16::from_be_bytes([pair[0], pair[1]]),
+                    false => u16::from_le_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(bytes).into_owned(),
+        _ => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Splits a text field at its first terminator, returning the text and the rest.
+pub fn split_text(encoding: u8, bytes: &[u8]) -> (String, &[u8]) {
+    let end = match encoding {
+        1 | 2 => bytes
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|i| (i * 2, i * 2 + 2)),
+        _ => bytes.iter().position(|&byte| byte == 0).map(|i| (i, i + 1)),
+    };
+
+    match end {
+        Some((text, rest)) => (decode_text(encoding, &bytes[..text]), &bytes[rest..]),
+        None => (decode_text(encoding, bytes), &[]),
+    }
+}
+
+fn txxx_description(frame: &Frame) -> Option<String> {
+    let (&encoding, rest) = frame.data.split_first()?;
+    (&frame.id == b"TXXX").then(|| split_text(encoding, rest).0)
+}
+
+/// Replaces the `TXXX` frames named in `fields` with the given values, creating
+/// an ID3v2.4 tag if the file has none.
+pub fn write_txxx<P>(path: P, fields: &[(&str, String)]) -> Result<(), String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).strerr()?;
+    let mut tag = Tag::parse(&bytes)?.unwrap_or(Tag {
+        major: 4,
+        frames: Vec::new(),
+        len: 0,
+    });
+
+    tag.frames.retain(|frame| {
+        txxx_description(frame).is_none_or(|description| {
+            fields
+                .iter()
+                .all(|(name, _)| !description.eq_ignore_ascii_case(name))
+        })
+    });
+
+    for (name, value) in fields {
+        let mut data = vec![0];
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(value.as_bytes());
+
+        tag.frames.push(Frame {
+            id: *b"TXXX",
+            flags: [0, 0],
+            data,
+        });
+    }
+
+    let frames = tag.encode_frames();
+
+    // a tag that still fits is rewritten where it is, leaving the audio alone
+    if tag.len >= HEADER_LEN + frames.len() {
+        let padding = tag.len - HEADER_LEN - frames.len();
+        let mut file = OpenOptions::new().write(true).open(path).strerr()?;
+        return file
+            .write_all(&Tag::encode(frames, tag.major, padding))
+            .strerr();
+    }
+
+    let mut out = Tag::encode(frames, tag.major, PADDING);
+    out.extend_from_slice(&bytes[tag.len..]);
+
+    // the whole file moves, so it is kept next to the original until it is copied
+    // back into it, which keeps the file's permissions, owner and hard links
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tagging");
+    std::fs::write(&temp, &out).strerr()?;
+
+    let copied = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&out));
+
+    match copied {
+        Ok(()) => std::fs::remove_file(&temp).strerr(),
+        Err(e) => Err(format!(
+            "{e}, the tagged file is kept as {}",
+            Path::new(&temp).display()
+        )),
+    }
+}
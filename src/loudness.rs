@@ -0,0 +1,255 @@
+use crate::{
+    cache,
+    eq::{Biquad, Coefficients},
+    log::{Log, log},
+    playback::Sample,
+    source::Source,
+    task::Task,
+};
+use std::{f64::consts::PI, path::Path, sync::atomic::AtomicBool};
+
+/// Measurement per ITU-R BS.1770.
+#[derive(Clone, Copy)]
+pub struct Loudness {
+    /// Gated integrated loudness in LUFS.
+    pub integrated: f32,
+    /// Linear true peak, from 4x oversampling.
+    pub true_peak: f32,
+}
+
+impl Loudness {
+    fn encode(&self) -> Vec<u8> {
+        self.integrated
+            .to_le_bytes()
+            .into_iter()
+            .chain(self.true_peak.to_le_bytes())
+            .collect()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (integrated, true_peak) = bytes.split_at_checked(4)?;
+
+        Some(Self {
+            integrated: f32::from_le_bytes(integrated.try_into().ok()?),
+            true_peak: f32::from_le_bytes(true_peak.try_into().ok()?),
+        })
+    }
+}
+
+const TAPS: usize = 12;
+const OVERSAMPLING: usize = 4;
+
+pub struct Analyzer {
+    channels: usize,
+    k_weighting: [Coefficients; 2],
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    sub_block_frames: usize,
+    sub_block_fill: usize,
+    sub_block_sums: Vec<f64>,
+    /// Channel-weighted mean square of every 100 ms sub-block.
+    sub_blocks: Vec<f64>,
+    phases: [[f32; TAPS]; OVERSAMPLING],
+    history: Vec<[Sample; TAPS]>,
+    true_peak: f32,
+}
+
+impl Analyzer {
+    const ABSOLUTE_GATE: f64 = -70.0;
+    const RELATIVE_GATE: f64 = -10.0;
+    const SUB_BLOCKS_PER_BLOCK: usize = 4;
+
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        // surround channels of a 5.1 layout are weighted up and the LFE is ignored
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (6, 3) => 0.0,
+                (6, 4 | 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+
+        Self {
+            channels,
+            k_weighting: k_weighting(sample_rate as f64),
+            filters: vec![[Biquad::default(); 2]; channels],
+            weights,
+            sub_block_frames: (sample_rate as usize / 10).max(1),
+            sub_block_fill: 0,
+            sub_block_sums: vec![0.0; channels],
+            sub_blocks: Vec::new(),
+            phases: polyphase(),
+            history: vec![[0.0; TAPS]; channels],
+            true_peak: 0.0,
+        }
+    }
+
+    pub fn feed(&mut self, block: &[Sample]) {
+        for frame in block.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let [shelf, highpass] = &mut self.filters[channel];
+                let weighted = highpass.process(
+                    &self.k_weighting[1],
+                    shelf.process(&self.k_weighting[0], sample),
+                );
+                self.sub_block_sums[channel] += (weighted as f64).powi(2);
+
+                let history = &mut self.history[channel];
+                history.rotate_right(1);
+                history[0] = sample;
+
+                for phase in self.phases.iter() {
+                    let interpolated: f32 =
+                        phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                    self.true_peak = interpolated.abs().max(self.true_peak);
+                }
+            }
+
+            self.sub_block_fill += 1;
+
+            if self.sub_block_fill == self.sub_block_frames {
+                let power = self
+                    .sub_block_sums
+                    .iter()
+                    .zip(self.weights.iter())
+                    .map(|(sum, weight)| weight * sum / self.sub_block_frames as f64)
+                    .sum();
+
+                self.sub_blocks.push(power);
+                self.sub_block_sums.fill(0.0);
+                self.sub_block_fill = 0;
+            }
+        }
+    }
+
+    /// Returns `None` if the signal never rises above the absolute gate.
+    pub fn finish(self) -> Option<Loudness> {
+        let to_lufs = |power: f64| -0.691 + 10.0 * power.log10();
+        let mean = |powers: &[f64]| powers.iter().sum::<f64>() / powers.len() as f64;
+
+        let blocks: Vec<f64> = self
+            .sub_blocks
+            .windows(Self::SUB_BLOCKS_PER_BLOCK)
+            .map(mean)
+            .filter(|&power| to_lufs(power) > Self::ABSOLUTE_GATE)
+            .collect();
+
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let relative_gate = to_lufs(mean(&blocks)) + Self::RELATIVE_GATE;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| to_lufs(power) > relative_gate)
+            .collect();
+
+        Some(Loudness {
+            integrated: to_lufs(mean(&gated)) as f32,
+            true_peak: self.true_peak,
+        })
+    }
+}
+
+/// The two K-weighting stages (high shelf, then high-pass), derived for any sample rate.
+fn k_weighting(sample_rate: f64) -> [Coefficients; 2] {
+    let shelf = {
+        const F0: f64 = 1681.974450955533;
+        const GAIN_DB: f64 = 3.999843853973347;
+        const Q: f64 = 0.7071752369554196;
+
+        let k = (PI * F0 / sample_rate).tan();
+        let vh = 10.0_f64.powf(GAIN_DB / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / Q + k * k;
+
+        Coefficients::raw(
+            ((vh + vb * k / Q + k * k) / a0) as f32,
+            (2.0 * (k * k - vh) / a0) as f32,
+            ((vh - vb * k / Q + k * k) / a0) as f32,
+            (2.0 * (k * k - 1.0) / a0) as f32,
+            ((1.0 - k / Q + k * k) / a0) as f32,
+        )
+    };
+
+    let highpass = {
+        const F0: f64 = 38.13547087602444;
+        const Q: f64 = 0.5003270373238773;
+
+        let k = (PI * F0 / sample_rate).tan();
+        let a0 = 1.0 + k / Q + k * k;
+
+        Coefficients::raw(
+            1.0,
+            -2.0,
+            1.0,
+            (2.0 * (k * k - 1.0) / a0) as f32,
+            ((1.0 - k / Q + k * k) / a0) as f32,
+        )
+    };
+
+    [shelf, highpass]
+}
+
+/// Hann-windowed sinc interpolator, split into one normalized filter per phase.
+fn polyphase() -> [[f32; TAPS]; OVERSAMPLING] {
+    let len = TAPS * OVERSAMPLING;
+    let center = (len - 1) as f64 / 2.0;
+
+    std::array::from_fn(|phase| {
+        let taps: [f64; TAPS] = std::array::from_fn(|k| {
+            let n = (phase + k * OVERSAMPLING) as f64;
+            let x = (n - center) / OVERSAMPLING as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n + 0.5) / len as f64).cos();
+            sinc * window
+        });
+        let sum: f64 = taps.iter().sum();
+
+        taps.map(|tap| (tap / sum) as f32)
+    })
+}
+
+const CACHE_KIND: &str = "loudness";
+
+/// Measures `path`, stopping early if `cancel` is raised.
+pub fn analyze<P>(path: P, cancel: &AtomicBool) -> Result<Option<Loudness>, String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if let Some(loudness) = cache::load(CACHE_KIND, path).and_then(|bytes| Loudness::decode(&bytes))
+    {
+        return Ok(Some(loudness));
+    }
+
+    let mut source = Source::open(path)?;
+    let mut analyzer = Analyzer::new(source.sample_rate, source.channels);
+    let total_frames = source.total_frames;
+
+    if !source.for_each_block(0, total_frames, cancel, |_, block| analyzer.feed(block)) {
+        return Ok(None);
+    }
+
+    let loudness = analyzer.finish().ok_or("silent track")?;
+
+    if let Err(e) = cache::store(CACHE_KIND, path, &loudness.encode()) {
+        log(Log::Warning, format!("failed to cache loudness ({e})"));
+    }
+
+    Ok(Some(loudness))
+}
+
+/// Measures `path` in the background unless its result is cached.
+pub fn spawn<P>(path: P) -> Task<Loudness>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().to_path_buf();
+    Task::spawn("loudness analysis", move |cancel| analyze(&path, cancel))
+}
@@ -1,120 +1,720 @@
+mod bookmarks;
+mod cache;
+mod chapters;
+mod cli;
+mod config;
+mod cue;
+mod dirs;
+mod dsp;
+mod dynamics;
 mod engine;
+mod eq;
+mod gain;
+mod id3;
 mod log;
+mod loudness;
+mod meter;
 mod playback;
+mod playlist;
+mod queue;
+mod scanner;
+mod session;
 mod smooth;
+mod source;
+mod speed;
+mod stereo;
 mod strerr;
+mod task;
+mod volume;
+mod wav;
+mod waveform;
 
+use bookmarks::{Bookmark, Bookmarks};
+use clap::Parser;
+use cli::{Cli, Command, Filters, Play};
+use config::{Action, Config};
+use dynamics::{Compressor, Limiter};
 use engine::Engine;
+use eq::{Equalizer, Preset};
+use gain::{GainMode, ReplayGain};
 use log::{Log, log};
+use meter::Meters;
 use playback::Playback;
-use rand::seq::SliceRandom;
+use queue::{Queue, Track};
+use scanner::{Rank, Scan, Scanner};
 use sdl2::{
-    audio::AudioDevice, event::Event, keyboard::Keycode, mouse::MouseButton, pixels::Color,
+    audio::{AudioCallback, AudioDevice},
+    event::Event,
+    keyboard::Mod,
+    mouse::MouseButton,
+    pixels::Color,
     rect::Rect,
 };
+use session::Session;
 use smooth::{Smooth, TimedSmooth};
-use std::path::{Path, PathBuf};
+use speed::SpeedMap;
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+use stereo::{Crossfeed, StereoWidth, VocalRemover};
 use strerr::Strerr;
+use task::Task;
+use volume::VolumeLaw;
+use wav::WavWriter;
 
 fn main() {
-    if let Err(e) = practically_main() {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Some(Command::Scan {
+            paths,
+            write_tags,
+            filters,
+        }) => scan_main(paths, &filters, write_tags),
+        Some(Command::Info { file }) => info_main(&file),
+        Some(Command::Render {
+            file,
+            output,
+            speed,
+            volume,
+        }) => render_main(&file, &output, speed, volume),
+        Some(Command::Progress) => progress_main(),
+        None => practically_main(cli.play),
+    };
+
+    if let Err(e) = result {
         log(Log::Error, e);
     }
 }
 
-fn collect_pathbufs<P>(path: P, extension: &str) -> Result<Vec<PathBuf>, String>
-where
-    P: AsRef<Path>,
-{
-    let body = |path: PathBuf| {
-        if path.is_file() {
-            path.extension()
-                .map(|str| str == extension)
-                .and_then(|ext_matched| if ext_matched { Some(vec![path]) } else { None })
-        } else if path.is_dir() {
-            collect_pathbufs(path, extension).ok()
-        } else {
-            None
+/// Replaces each `-` with the newline-separated paths read from stdin.
+fn read_stdin_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    let mut out = Vec::new();
+
+    for path in paths {
+        if path.as_os_str() != "-" {
+            out.push(path);
+            continue;
         }
-    };
 
-    Ok(std::fs::read_dir(path)
-        .strerr()?
-        .filter_map(|entry_result| entry_result.ok())
-        .filter_map(|entry| body(entry.path()))
-        .flatten()
-        .collect())
+        for line in std::io::stdin().lock().lines() {
+            let line = line.strerr()?;
+            let line = line.trim_end_matches('\r');
+
+            if !line.is_empty() {
+                out.push(PathBuf::from(line));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// What a scan collects according to the command line.
+fn scanner(filters: &Filters) -> Scanner {
+    Scanner::new(
+        &filters.extensions,
+        &filters.include,
+        &filters.exclude,
+        filters.hidden,
+    )
+}
+
+/// `mp3 scan <paths>... [--write-tags]`: measures the loudness of every file,
+/// filling the cache and optionally writing ReplayGain tags.
+fn scan_main(paths: Vec<PathBuf>, filters: &Filters, write_tags: bool) -> Result<(), String> {
+    let mut files = Scan::spawn(read_stdin_paths(paths)?, scanner(filters)).wait();
+    // the tracks of a cue sheet share one file, which is measured once
+    files.dedup_by(|a, b| a.path == b.path);
+    let next = AtomicUsize::new(0);
+    let never = AtomicBool::new(false);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    log(Log::Info, format!("analyzing {} file(s)", files.len()));
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while let Some(track) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let file = &track.path;
+                    let name = file.display();
+                    let loudness = match loudness::analyze(file, &never) {
+                        Ok(Some(loudness)) => loudness,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            log(Log::Warning, format!("{name}: {e}"));
+                            continue;
+                        }
+                    };
+
+                    let gain = ReplayGain::REFERENCE_LUFS - loudness.integrated;
+                    let peak_db = dsp::to_db(loudness.true_peak);
+                    log(
+                        Log::Info,
+                        format!(
+                            "{:.1} LUFS, {peak_db:+.1} dBTP, gain {gain:+.2} dB | {name}",
+                            loudness.integrated
+                        ),
+                    );
+
+                    let is_mp3 = file
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+
+                    if write_tags && !is_mp3 {
+                        log(
+                            Log::Warning,
+                            format!("{name}: tags are only written to MP3 files"),
+                        );
+                    } else if write_tags {
+                        let fields = [
+                            ("REPLAYGAIN_TRACK_GAIN", format!("{gain:.2} dB")),
+                            (
+                                "REPLAYGAIN_TRACK_PEAK",
+                                format!("{:.6}", loudness.true_peak),
+                            ),
+                        ];
+
+                        if let Err(e) = id3::write_txxx(file, &fields) {
+                            log(Log::Warning, format!("{name}: writing tags failed ({e})"));
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// The file the current track remembers its position under. Cue sheet tracks
+/// share their file, so only whole files do.
+fn bookmark_path(queue: &Queue) -> Option<&Path> {
+    queue
+        .current_track()
+        .filter(|track| !track.is_cue_track())
+        .map(|track| track.path.as_path())
+}
+
+/// Writes whatever changed on its own thread, so the window never waits for the disk.
+fn save_on_thread(bookmarks: Option<Bookmarks>, session: Option<Arc<Session>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        if let Some(bookmarks) = bookmarks
+            && let Err(e) = bookmarks.save()
+        {
+            log(Log::Warning, format!("failed to save positions ({e})"));
+        }
+
+        if let Some(session) = session
+            && let Err(e) = session.save()
+        {
+            log(Log::Warning, format!("failed to save session ({e})"));
+        }
+    })
+}
+
+/// Formats seconds as `h:mm:ss`.
+fn clock(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// `mp3 info <file>`: stream parameters and loudness tags.
+fn info_main(file: &Path) -> Result<(), String> {
+    let source = source::Source::open(file)?;
+    let db = |gain: Option<f32>| gain.map_or("none".to_string(), |gain| format!("{gain:+.2} dB"));
+    let peak = |peak: Option<f32>| peak.map_or("none".to_string(), |peak| format!("{peak:.6}"));
+    let gain = source.replay_gain;
+
+    log(Log::Info, file.display());
+    log(Log::Info, format!("sample rate: {} Hz", source.sample_rate));
+    log(Log::Info, format!("channels: {}", source.channels));
+    log(
+        Log::Info,
+        format!(
+            "duration: {}",
+            clock(source.total_frames as f64 / source.sample_rate as f64)
+        ),
+    );
+    log(
+        Log::Info,
+        format!(
+            "track gain: {}, peak: {}",
+            db(gain.track_gain),
+            peak(gain.track_peak)
+        ),
+    );
+    log(
+        Log::Info,
+        format!(
+            "album gain: {}, peak: {}",
+            db(gain.album_gain),
+            peak(gain.album_peak)
+        ),
+    );
+
+    Ok(())
+}
+
+/// `mp3 render <file> -o <wav>`: runs the playback path offline, effects and
+/// limiter included, with loudness measured first for untagged files.
+fn render_main(file: &Path, output: &Path, speed: f32, volume: f32) -> Result<(), String> {
+    const BLOCK_FRAMES: usize = 4096;
+
+    let mut playback = Playback::new(file, None)?;
+
+    if !playback.is_tagged()
+        && let Some(loudness) = loudness::analyze(file, &AtomicBool::new(false))?
+    {
+        playback.set_loudness(&loudness);
+    }
+
+    playback.speed = Smooth::new(speed);
+    playback.volume = Smooth::new(volume);
+
+    let mut writer = WavWriter::create(output, playback.sample_rate, playback.channels)?;
+    let mut block = vec![0.0; BLOCK_FRAMES * playback.channels];
+
+    log(
+        Log::Info,
+        format!("rendering {} to {}", file.display(), output.display()),
+    );
+
+    // one more block after the end flushes the limiter's lookahead
+    let mut flushed = false;
+
+    while !flushed {
+        flushed = playback.end;
+        playback.callback(&mut block);
+        writer.write(&block)?;
+    }
+
+    writer.finish()
+}
+
+fn progress_main() -> Result<(), String> {
+    let bookmarks = Bookmarks::load()?;
+    let mut any = false;
+
+    for (path, bookmark) in bookmarks.in_progress() {
+        let percent = bookmark.position / bookmark.duration.max(1.0) * 100.0;
+        log(
+            Log::Info,
+            format!(
+                "{percent:>3.0}% {} / {} at {:.2}x | {}",
+                clock(bookmark.position),
+                clock(bookmark.duration),
+                bookmark.speed,
+                path.display()
+            ),
+        );
+        any = true;
+    }
+
+    if !any {
+        log(Log::Info, "nothing in progress");
+    }
+
+    Ok(())
 }
 
-fn practically_main() -> Result<(), String> {
+fn practically_main(play: Play) -> Result<(), String> {
     const TITLE: &str = "Suika's MP3 Player";
     const WIDTH: u32 = 800;
-    const HEIGHT: u32 = 200;
+    const METER_HEIGHT: u32 = 5;
+    const LED_WIDTH: u32 = 12;
+    const WAVEFORM_HEIGHT: u32 = 40;
+    const HALF_HEIGHT: u32 = 100;
+    const HEIGHT: u32 = WAVEFORM_HEIGHT + HALF_HEIGHT * 2;
     const SIZE: (u32, u32) = (WIDTH, HEIGHT);
-    const HALF_HEIGHT: u32 = HEIGHT / 2;
-    const SPEED_ORIGIN: i32 = WIDTH as i32 * 2 / 5;
-    const VOLUME_ORIGIN: i32 = WIDTH as i32 / 4;
-    const TRANSITION_DURATION: f32 = 0.3;
+    const SPEED_TOP: i32 = WAVEFORM_HEIGHT as i32;
+    const VOLUME_TOP: i32 = SPEED_TOP + HALF_HEIGHT as i32;
+    const METER_TOP: i32 = HEIGHT as i32 - 2 * METER_HEIGHT as i32;
+    const EQ_CENTER: i32 = (SPEED_TOP + METER_TOP) / 2;
+    const EQ_RANGE: f32 = (METER_TOP - SPEED_TOP) as f32 / 2.0 - 4.0;
     const CH_LENGTH: i32 = 8;
     const CH_THICKNESS: u32 = 2;
-    const COLOR_SPEED: Color = Color::RGB(0x00, 0x31, 0x61);
-    const COLOR_VOLUME: Color = Color::RGB(0x00, 0x6A, 0x67);
     const WHITE: Color = Color::RGB(0xFF, 0xFF, 0xFF);
     const FONT_SIZE: u16 = 48;
-    const FF_INTENSITY: f32 = 32.0;
+    const SMALL_FONT_SIZE: u16 = 16;
+    const ROW_HEIGHT: i32 = SMALL_FONT_SIZE as i32 + 8;
+    const STATUS_CHARS: usize = 40;
+    /// Drag velocity that plays at 1x while scrubbing.
+    const SCRUB_PX_PER_SEC: f32 = 400.0;
+    const SCRUB_SMOOTHING: f32 = 0.5;
+    const KEY_STEP: f32 = WIDTH as f32 / 40.0;
+    const SPEED_PRESETS: [(Action, f32); 6] = [
+        (Action::SpeedPreset1, 0.5),
+        (Action::SpeedPreset2, 0.75),
+        (Action::SpeedPreset3, 1.0),
+        (Action::SpeedPreset4, 1.25),
+        (Action::SpeedPreset5, 1.5),
+        (Action::SpeedPreset6, 2.0),
+    ];
 
-    let files = {
-        let path = std::env::args().nth(1).ok_or(
-            "please provide a folder containing mp3 files, which will be scanned recursively",
-        )?;
+    // a broken config is reported and left alone rather than overwritten on exit
+    let (mut config, config_ok) = match Config::load() {
+        Ok(config) => (config, true),
+        Err(e) => {
+            log(
+                Log::Error,
+                format!("{e}\nusing default settings, the file will not be overwritten"),
+            );
+            (Config::default(), false)
+        }
+    };
+    let bindings = config.bindings()?;
+    let theme = config.theme;
+
+    // stdin is read up front, so a session resumes only for the same list
+    let roots = match play.paths.is_empty() {
+        true => config.library.clone(),
+        false => read_stdin_paths(play.paths)?,
+    };
+
+    if roots.is_empty() {
+        return Err(
+            "please provide files or folders to play, folders are scanned recursively \
+             (or list folders under `library` in the config file)"
+                .into(),
+        );
+    }
 
-        log(Log::Info, "collecting mp3 files... this might take a while");
-        let mut files = collect_pathbufs(path, "mp3")?;
-        let mut rng = rand::rng();
-        files.shuffle(&mut rng);
-        files
+    // absolute, so the same folders match, and the queue's paths resolve, from any working directory
+    let roots: Vec<_> = roots
+        .iter()
+        .map(|root| bookmarks::canonical(root))
+        .collect();
+
+    let session = if play.fresh {
+        None
+    } else {
+        Session::load()
+            .unwrap_or_else(|e| {
+                log(Log::Warning, format!("{e}, starting a new session"));
+                None
+            })
+            .filter(|session| session.roots == roots)
+            .filter(|session| {
+                // a track that cannot be opened any more would only end the player again
+                let opened = session
+                    .queue
+                    .current_track()
+                    .ok_or_else(|| "queue is empty".to_string())
+                    .and_then(|track| {
+                        source::Source::open(&track.path)
+                            .map(|_| ())
+                            .map_err(|e| format!("{}: {e}", track.path.display()))
+                    });
+
+                if let Err(e) = &opened {
+                    log(Log::Warning, format!("{e}, starting a new session"));
+                }
+                opened.is_ok()
+            })
+    };
+
+    let shuffle = config.shuffle && !play.no_shuffle;
+    let (mut queue, mut scan, resume_at, speed, volume) = match session {
+        Some(session) => {
+            log(
+                Log::Info,
+                "resuming the last session (pass --fresh to start over)",
+            );
+            (
+                session.queue,
+                None,
+                session.position,
+                session.speed,
+                session.volume,
+            )
+        }
+        None => (
+            Queue::new(Vec::new(), shuffle, play.seed, config.repeat),
+            Some(Scan::spawn(roots.clone(), scanner(&play.filters))),
+            0.0,
+            config.speed,
+            config.volume,
+        ),
+    };
+    // where each track found by the scan goes once it is done, in the order they were added
+    let mut ranks: Vec<Rank> = Vec::new();
+    let absorb = |queue: &mut Queue, ranks: &mut Vec<Rank>, found: Vec<(Rank, Track)>| {
+        let (found_ranks, tracks): (Vec<_>, Vec<_>) = found.into_iter().unzip();
+        ranks.extend(found_ranks);
+        queue.extend(tracks);
     };
 
-    log(Log::Info, format!("{} mp3 file(s) found", files.len()));
+    if play.no_shuffle && queue.is_shuffled() {
+        queue.set_shuffle(false);
+    }
+
+    if let Some(repeat) = play.repeat {
+        queue.repeat = repeat;
+    }
 
-    let mut files = files.iter();
-    let mut load = |engine: &mut Engine, device: Option<AudioDevice<Playback>>| {
-        let pathbuf = files.next().ok_or("queue is empty")?;
-        let pathstr = pathbuf
-            .file_name()
-            .and_then(|osstr| osstr.to_str())
-            .unwrap_or("<broken ahh filename>");
-        let queue = files.len();
+    // flags and resumed sessions only last for this run, T and H are what change the config
+    let (shuffle_start, repeat_start) = (queue.is_shuffled(), queue.repeat);
 
-        engine.set_title(&format!("{TITLE} | Queue: {queue} | File: {pathstr}"))?;
-        engine.load_device(
+    let (mut bookmarks, bookmarks_ok) = match Bookmarks::load() {
+        Ok(bookmarks) => (bookmarks, true),
+        Err(e) => {
+            log(
+                Log::Error,
+                format!("{e}\nremembered positions are disabled, the file will not be overwritten"),
+            );
+            (Bookmarks::default(), false)
+        }
+    };
+    let bookmark_config = config.bookmarks.clone();
+    // positions are read out of the playback first, so the audio lock is never held for file I/O
+    let remember = |bookmarks: &mut Bookmarks, path: &Path, (position, duration), speed| {
+        if bookmark_config.applies(path, duration) {
+            bookmarks.remember(
+                path,
+                Bookmark {
+                    position,
+                    duration,
+                    speed,
+                    finished: false,
+                },
+            );
+        }
+    };
+    let position = |device: &mut AudioDevice<Playback>| {
+        let playback = device.lock();
+        (playback.position_secs(), playback.duration_secs())
+    };
+
+    let session = |queue: &Queue, position: f64, speed: f32, volume: f32| {
+        Arc::new(Session {
+            roots: roots.clone(),
+            position,
+            speed,
+            volume,
+            queue: queue.clone(),
+        })
+    };
+    let title = |queue: &Queue, chapter: Option<&str>| {
+        let pathstr = queue.current_track().map_or("", |track| {
+            track.title.as_deref().unwrap_or_else(|| {
+                track
+                    .path
+                    .file_name()
+                    .and_then(|osstr| osstr.to_str())
+                    .unwrap_or("<broken ahh filename>")
+            })
+        });
+        let queue = queue.remaining();
+
+        match chapter {
+            Some(chapter) => {
+                format!("{TITLE} | Queue: {queue} | File: {pathstr} | Chapter: {chapter}")
+            }
+            None => format!("{TITLE} | Queue: {queue} | File: {pathstr}"),
+        }
+    };
+    let load = |engine: &mut Engine, queue: &Queue, device: Option<AudioDevice<Playback>>| {
+        let track = queue.current_track().ok_or("queue is empty")?;
+        let pathbuf = &track.path;
+
+        engine.set_title(&title(queue, None))?;
+        let mut device = engine.load_device(
             pathbuf,
-            device.map(|d| d.close_and_get_callback().take_buffer()),
-        )
+            track.span(),
+            device.map(|d| d.close_and_get_callback().into_carry()),
+        )?;
+        let loudness = (!device.lock().is_tagged()).then(|| loudness::spawn(pathbuf));
+        let waveform = waveform::spawn(pathbuf, track.span(), WIDTH as usize);
+
+        Ok::<_, String>((device, waveform, loudness))
     };
 
     let mut engine = Engine::new(TITLE, SIZE)?;
 
     let ttf_context = sdl2::ttf::init().strerr()?;
     let font = ttf_context.load_font("CascadiaMono.ttf", FONT_SIZE)?;
-
-    let mut device = load(&mut engine, None)?;
+    let small_font = ttf_context.load_font("CascadiaMono.ttf", SMALL_FONT_SIZE)?;
     let mut event_pump = engine.event_pump()?;
-    let mut s_speed = TimedSmooth::new(SPEED_ORIGIN as f32, TRANSITION_DURATION);
-    let mut s_volume = TimedSmooth::new(VOLUME_ORIGIN as f32, TRANSITION_DURATION);
-    let mut s_progress = TimedSmooth::new(0.0, TRANSITION_DURATION);
+
+    // the window shows the scan until the first track turns up. Unshuffled or seeded,
+    // the whole library is waited for, as the order depends on all of it
+    let ordered = !shuffle || play.seed.is_some();
+    let mut pending = Vec::new();
+
+    while let Some(running) = scan.as_mut()
+        && (queue.is_empty() || ordered)
+    {
+        for event in event_pump.poll_iter() {
+            if let Event::Quit { .. } = event {
+                return Ok(());
+            }
+        }
+
+        let found = running.poll();
+
+        match ordered {
+            true => pending.extend(found),
+            false => absorb(&mut queue, &mut ranks, found),
+        }
+
+        if running.is_done() {
+            if ordered {
+                queue = Queue::new(
+                    scanner::into_order(std::mem::take(&mut pending)),
+                    shuffle,
+                    play.seed,
+                    queue.repeat,
+                );
+            } else {
+                queue.sort_files(&ranks);
+            }
+            scan = None;
+            break;
+        }
+
+        let (files, folders) = running.progress();
+        let surface = small_font
+            .render(&format!("scanning... {files} found in {folders} folder(s)"))
+            .blended(WHITE)
+            .strerr()?;
+        engine.clear();
+        engine.draw_surface(surface, (4, 4))?;
+        engine.present();
+    }
+
+    if queue.is_empty() {
+        return Err("nothing to play was found".to_string());
+    }
+
+    // a bookmarked first track resumes from its bookmark unless the session already does,
+    // and its speed only lasts until a track without one
+    let initial = bookmark_path(&queue)
+        .and_then(|path| bookmarks.get(path))
+        .copied();
+    let mut normal_speed = initial.map(|_| config.speed);
+    let (resume_at, speed) = match initial {
+        Some(bookmark) if resume_at == 0.0 => (bookmark.position, bookmark.speed),
+        _ => (resume_at, speed),
+    };
+    let resume_at = play.start_at.unwrap_or(resume_at);
+    let speed = play.speed.unwrap_or(speed);
+    let volume = play.volume.unwrap_or(volume);
+
+    let (mut device, mut waveform, mut loudness) = load(&mut engine, &queue, None)?;
+
+    // the chain is carried from track to track, so this lasts the whole session
+    if let Some(compressor) = device.lock().chain.get_mut::<Compressor>() {
+        config.night.apply(compressor);
+    }
+
+    if resume_at > 0.0
+        && let Err(e) = device.lock().seek_secs(resume_at)
+    {
+        log(Log::Warning, format!("failed to resume position ({e})"));
+    }
+
+    let mut speed_map = config.speed_map;
+    let speed_origin = |map: &SpeedMap| (map.detent * WIDTH as f32) as i32;
+    let mut s_speed = TimedSmooth::new(speed_map.fraction(speed) * WIDTH as f32, config.transition);
+    let mut volume_law = config.volume_law;
+    let mut volume_db = false;
+    let volume_origin = |law: VolumeLaw| (law.origin() * WIDTH as f32) as i32;
+    let mut s_volume = TimedSmooth::new(
+        volume_law.fraction(volume) * WIDTH as f32,
+        config.transition,
+    );
+    // like repeat and shuffle, the speed and volume only go into the config once changed here
+    let speed_start =
+        normal_speed.unwrap_or_else(|| speed_map.speed(s_speed.target() / WIDTH as f32));
+    let volume_start = volume_law.gain(s_volume.target() / WIDTH as f32);
+    let mut s_progress = TimedSmooth::new(0.0, config.transition);
+    let mut s_direction = TimedSmooth::new(1.0, config.transition);
+    let mut reverse = false;
+    let mut s_scrub = TimedSmooth::new(0.0, config.transition);
+    let mut scrubbing = false;
+    let mut scrub_dx = 0;
+    let mut scrub_velocity = 0.0;
+    let mut frame_prev = Instant::now();
+    let mut session_saved = Instant::now();
+    let mut last_session = None;
+    let mut saving: Option<JoinHandle<()>> = None;
+    let mut skip = false;
+    let mut back = false;
+    let mut finished = false;
     let mut progress_prev = 0.0;
+    let mut meters = Meters::new();
+    let mut eq_gains = Preset::Flat.gains();
+    let mut eq_preset = Some(Preset::Flat);
+    let mut panel = None;
+    let mut effects = Vec::new();
+    let mut gain_mode = GainMode::Track;
+    let mut pre_gain_db = 0.0;
+    let mut night = false;
+    let mut limiter = true;
+    let mut crossfeed = false;
+    let mut karaoke = false;
+    let mut width = 1.0;
     let mut scroll = None;
+    let mut seek = None;
+    let mut loop_points = (None, None);
+    let mut chapter_marks = Vec::new();
+    let mut chapter_shown = None;
+
+    #[derive(PartialEq)]
+    enum Panel {
+        Equalizer,
+        Chain,
+    }
 
     enum Which {
+        Waveform,
+        Equalizer,
+        Chain(usize),
         Speed,
         Volume,
     }
 
+    let speed_x = |map: &SpeedMap, x: i32| map.snap(x as f32 / WIDTH as f32) * WIDTH as f32;
+    let step = |smooth: &mut TimedSmooth, by: f32| {
+        smooth.shift_set((smooth.target() + by).clamp(0.0, WIDTH as f32))
+    };
+
+    let eq_band =
+        |x: i32| (x as usize * Equalizer::BANDS / WIDTH as usize).min(Equalizer::BANDS - 1);
+    let eq_gain = |y: i32| {
+        ((EQ_CENTER - y) as f32 / EQ_RANGE * Equalizer::MAX_GAIN_DB)
+            .clamp(-Equalizer::MAX_GAIN_DB, Equalizer::MAX_GAIN_DB)
+    };
+
     'main_loop: loop {
         s_speed.update();
         s_volume.update();
         s_progress.update();
+        s_direction.update();
+        s_scrub.update();
+
+        // tracks keep coming in while the rest of the library is scanned
+        if let Some(running) = scan.as_mut() {
+            let found = running.poll();
+
+            if !found.is_empty() {
+                absorb(&mut queue, &mut ranks, found);
+                engine.set_title(&title(&queue, chapter_shown.as_deref()))?;
+            }
+
+            if running.is_done() {
+                queue.sort_files(&ranks);
+                ranks = Vec::new();
+                scan = None;
+            }
+        }
 
         let (mouse_left, mouse_right, mouse_x, mouse_y) = {
             let mouse = sdl2::mouse::MouseState::new(&event_pump);
@@ -126,7 +726,17 @@ fn practically_main() -> Result<(), String> {
             )
         };
 
-        let which = if mouse_y < HALF_HEIGHT as i32 {
+        let mod_state = engine.sdl_context.keyboard().mod_state();
+        let shift = mod_state.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let ctrl = mod_state.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+
+        let which = if mouse_y < SPEED_TOP {
+            Which::Waveform
+        } else if panel == Some(Panel::Equalizer) {
+            Which::Equalizer
+        } else if panel == Some(Panel::Chain) {
+            Which::Chain(((mouse_y - SPEED_TOP) / ROW_HEIGHT) as usize)
+        } else if mouse_y < VOLUME_TOP {
             Which::Speed
         } else {
             Which::Volume
@@ -136,65 +746,303 @@ fn practically_main() -> Result<(), String> {
             match event {
                 Event::Quit { .. } => break 'main_loop,
                 Event::KeyDown {
-                    keycode: Some(Keycode::SPACE),
+                    keycode: Some(keycode),
                     ..
-                } => device = load(&mut engine, Some(device))?,
+                } => match bindings.get(&keycode) {
+                    Some(Action::Next) => skip = true,
+                    Some(Action::Previous) => back = true,
+                    Some(Action::NextChapter) => {
+                        if let Err(e) = device.lock().next_chapter() {
+                            log(Log::Warning, format!("chapter skip failed ({e})"));
+                        }
+                    }
+                    Some(Action::PreviousChapter) => {
+                        if let Err(e) = device.lock().previous_chapter() {
+                            log(Log::Warning, format!("chapter skip failed ({e})"));
+                        }
+                    }
+                    Some(Action::Finished) => {
+                        if let Some(path) = bookmark_path(&queue) {
+                            bookmarks.finish(path);
+                        }
+                        finished = true;
+                    }
+                    Some(Action::Equalizer) => {
+                        panel = match panel {
+                            Some(Panel::Equalizer) => None,
+                            _ => Some(Panel::Equalizer),
+                        }
+                    }
+                    Some(Action::GainMode) => gain_mode = gain_mode.next(),
+                    Some(Action::Night) => {
+                        let chain = &mut device.lock().chain;
+                        if let Some(index) = chain.index_of::<Compressor>() {
+                            chain.toggle(index);
+                        }
+                    }
+                    Some(Action::Limiter) => {
+                        let output = &mut device.lock().output;
+                        if let Some(index) = output.index_of::<Limiter>() {
+                            output.toggle(index);
+                        }
+                    }
+                    Some(Action::VolumeLaw) => {
+                        // keep the gain where it is, only the bar position changes
+                        let next = volume_law.next();
+                        let gain = volume_law.gain(s_volume.interpolate() / WIDTH as f32);
+                        s_volume.reset(next.fraction(gain) * WIDTH as f32);
+                        volume_law = next;
+                    }
+                    Some(Action::DbReadout) => volume_db = !volume_db,
+                    Some(Action::SpeedCurve) => {
+                        // keep the speed where it is, only the bar position changes
+                        let speed = speed_map.speed(s_speed.interpolate() / WIDTH as f32);
+                        speed_map.curve = speed_map.curve.next();
+                        s_speed.reset(speed_map.fraction(speed) * WIDTH as f32);
+                    }
+                    Some(Action::SpeedSnap) => {
+                        speed_map.snap = match speed_map.snap {
+                            Some(_) => None,
+                            None => Some(SpeedMap::SNAP),
+                        }
+                    }
+                    Some(Action::SpeedDown) => step(&mut s_speed, -KEY_STEP),
+                    Some(Action::SpeedUp) => step(&mut s_speed, KEY_STEP),
+                    Some(Action::VolumeUp) => step(&mut s_volume, KEY_STEP),
+                    Some(Action::VolumeDown) => step(&mut s_volume, -KEY_STEP),
+                    Some(Action::Reset) => {
+                        s_speed.shift_set(speed_origin(&speed_map) as f32);
+                        s_volume.shift_set(volume_origin(volume_law) as f32);
+                    }
+                    Some(Action::Crossfeed) => {
+                        let chain = &mut device.lock().chain;
+                        if let Some(index) = chain.index_of::<Crossfeed>() {
+                            chain.toggle(index);
+                        }
+                    }
+                    Some(Action::Karaoke) => {
+                        let chain = &mut device.lock().chain;
+                        if let Some(index) = chain.index_of::<VocalRemover>() {
+                            chain.toggle(index);
+                        }
+                    }
+                    Some(Action::Narrow) => width = (width - 0.1_f32).max(0.0),
+                    Some(Action::Widen) => width = (width + 0.1_f32).min(StereoWidth::MAX),
+                    Some(Action::LoopA) => {
+                        let mut device = device.lock();
+                        let progress = device.progress();
+                        device.set_loop_a(progress);
+                    }
+                    Some(Action::LoopB) => {
+                        let mut device = device.lock();
+                        let progress = device.progress();
+                        device.set_loop_b(progress);
+                    }
+                    Some(Action::ClearLoop) => device.lock().clear_loop(),
+                    Some(Action::Reverse) => {
+                        reverse = !reverse;
+                        s_direction.shift_set(if reverse { -1.0 } else { 1.0 });
+                    }
+                    Some(Action::Chain) => {
+                        panel = match panel {
+                            Some(Panel::Chain) => None,
+                            _ => Some(Panel::Chain),
+                        }
+                    }
+                    Some(Action::EqPreset) => {
+                        let preset = eq_preset.map_or(Preset::Flat, Preset::next);
+                        eq_gains = preset.gains();
+                        eq_preset = Some(preset);
+                    }
+                    Some(action)
+                        if let Some(&(_, speed)) =
+                            SPEED_PRESETS.iter().find(|(preset, _)| preset == action) =>
+                    {
+                        s_speed.shift_set(speed_map.fraction(speed) * WIDTH as f32);
+                    }
+                    Some(Action::Repeat) => queue.repeat = queue.repeat.next(),
+                    Some(Action::Shuffle) => queue.set_shuffle(!queue.is_shuffled()),
+                    Some(Action::SaveQueue) => {
+                        let name = chrono::Local::now().format("queue-%Y-%m-%d_%H-%M-%S.m3u8");
+                        let saved = dirs::data_dir()
+                            .ok_or_else(|| "no data directory".to_string())
+                            .map(|dir| dir.join("playlists").join(name.to_string()))
+                            .and_then(|path| {
+                                playlist::save_m3u8(&path, &queue.tracks()).map(|_| path)
+                            });
+
+                        match saved {
+                            Ok(path) => {
+                                log(Log::Info, format!("queue saved to {}", path.display()))
+                            }
+                            Err(e) => log(Log::Warning, format!("failed to save queue ({e})")),
+                        }
+                    }
+                    _ => (),
+                },
                 Event::MouseButtonDown { mouse_btn, .. } => match mouse_btn {
+                    MouseButton::Left if ctrl && !mouse_right => {
+                        scrubbing = true;
+                        scrub_dx = 0;
+                        scrub_velocity = 0.0;
+                        s_scrub.reset(1.0);
+                    }
                     MouseButton::Left if !mouse_right => match which {
-                        Which::Speed => s_speed.shift_set(mouse_x as f32),
+                        Which::Waveform if shift => {
+                            device.lock().set_loop_a(mouse_x as f32 / WIDTH as f32)
+                        }
+                        Which::Waveform => seek = Some(mouse_x as f32 / WIDTH as f32),
+                        Which::Equalizer => {
+                            eq_gains[eq_band(mouse_x)] = eq_gain(mouse_y);
+                            eq_preset = None;
+                        }
+                        Which::Chain(row) => device.lock().chain.toggle(row),
+                        Which::Speed => s_speed.shift_set(speed_x(&speed_map, mouse_x)),
                         Which::Volume => s_volume.shift_set(mouse_x as f32),
                     },
                     MouseButton::Right => match which {
-                        Which::Speed => s_speed.shift_set(SPEED_ORIGIN as f32),
-                        Which::Volume => s_volume.shift_set(VOLUME_ORIGIN as f32),
+                        Which::Waveform if shift => {
+                            device.lock().set_loop_b(mouse_x as f32 / WIDTH as f32)
+                        }
+                        Which::Waveform => (),
+                        Which::Equalizer => {
+                            eq_gains[eq_band(mouse_x)] = 0.0;
+                            eq_preset = None;
+                        }
+                        Which::Chain(_) => (),
+                        Which::Speed => s_speed.shift_set(speed_origin(&speed_map) as f32),
+                        Which::Volume => s_volume.shift_set(volume_origin(volume_law) as f32),
                     },
                     _ => (),
                 },
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } if scrubbing => {
+                    scrubbing = false;
+                    s_scrub.shift_set(0.0);
+                }
+                Event::MouseMotion { xrel, .. } if scrubbing => scrub_dx += xrel,
                 Event::MouseMotion { .. } if mouse_left && !mouse_right => match which {
-                    Which::Speed => s_speed.shift_set(mouse_x as f32),
+                    Which::Waveform => (),
+                    Which::Equalizer => {
+                        eq_gains[eq_band(mouse_x)] = eq_gain(mouse_y);
+                        eq_preset = None;
+                    }
+                    Which::Chain(_) => (),
+                    Which::Speed => s_speed.shift_set(speed_x(&speed_map, mouse_x)),
                     Which::Volume => s_volume.shift_set(mouse_x as f32),
                 },
-                Event::MouseWheel { y, .. } => scroll = Some(y),
+                Event::MouseWheel { y, .. } => match which {
+                    Which::Chain(row) => device.lock().chain.shift(row, -y),
+                    _ => scroll = Some(y),
+                },
                 _ => (),
             }
         }
 
-        let to_speed =
-            FF_INTENSITY.powf((s_speed.interpolate() - SPEED_ORIGIN as f32) / WIDTH as f32);
-        let to_volume = s_volume.interpolate() / WIDTH as f32;
+        // while scrubbing, the speed follows the pointer like a record under the needle
+        let frame_now = Instant::now();
+        if scrubbing {
+            let dt = (frame_now - frame_prev).as_secs_f32().max(1e-3);
+            let target = scrub_dx as f32 / dt / SCRUB_PX_PER_SEC;
+            scrub_velocity += (target - scrub_velocity) * SCRUB_SMOOTHING;
+            scrub_dx = 0;
+        }
+        frame_prev = frame_now;
+
+        let scrub = s_scrub.interpolate();
+        let to_speed = scrub * scrub_velocity
+            + (1.0 - scrub)
+                * s_direction.interpolate()
+                * speed_map.speed(s_speed.interpolate() / WIDTH as f32);
+        let to_volume = volume_law.gain(s_volume.interpolate() / WIDTH as f32);
         let surface_speed = font
             .render(&format!("{to_speed:.2}x"))
             .blended(WHITE)
             .strerr()?;
         let surface_volume = font
-            .render(&format!("{:.1}%", to_volume * 100.0))
+            .render(&if !volume_db {
+                format!("{:.1}%", to_volume * 100.0)
+            } else if to_volume > 0.0 {
+                format!("{:.1} dB", dsp::to_db(to_volume))
+            } else {
+                "mute".to_string()
+            })
             .blended(WHITE)
             .strerr()?;
 
         engine.clear();
 
+        // waveform, split at the playhead
+        if let Some(peaks) = waveform.poll() {
+            let playhead = (s_progress.interpolate() * WIDTH as f32) as i32;
+            let center = WAVEFORM_HEIGHT as f32 / 2.0;
+            let (played, unplayed): (Vec<_>, Vec<_>) = peaks
+                .iter()
+                .enumerate()
+                .map(|(x, &(min, max))| {
+                    let top = (center - max.clamp(-1.0, 1.0) * center) as i32;
+                    let bottom = (center - min.clamp(-1.0, 1.0) * center) as i32;
+                    Rect::new(x as i32, top, 1, (bottom - top).max(1) as u32)
+                })
+                .partition(|rect| rect.x() < playhead);
+
+            engine.draw_rects(&played, theme.played)?;
+            engine.draw_rects(&unplayed, theme.unplayed)?;
+            engine.draw_rect(Rect::new(playhead, 0, CH_THICKNESS, WAVEFORM_HEIGHT), WHITE)?;
+        }
+
+        // loop markers, joined along the bottom of the waveform once both are set
+        let to_x = |progress: f32| (progress * WIDTH as f32) as i32;
+
+        for point in [loop_points.0, loop_points.1].into_iter().flatten() {
+            engine.draw_rect(
+                Rect::new(to_x(point), 0, CH_THICKNESS, WAVEFORM_HEIGHT),
+                theme.looping,
+            )?;
+        }
+
+        if let (Some(a), Some(b)) = loop_points
+            && a < b
+        {
+            engine.draw_rect(
+                Rect::new(
+                    to_x(a),
+                    WAVEFORM_HEIGHT as i32 - CH_THICKNESS as i32,
+                    (to_x(b) - to_x(a)) as u32,
+                    CH_THICKNESS,
+                ),
+                theme.looping,
+            )?;
+        }
+
+        // chapter ticks, hanging from the top of the waveform
+        for &mark in &chapter_marks {
+            engine.draw_rect(
+                Rect::new(to_x(mark), 0, CH_THICKNESS, CH_LENGTH as u32),
+                theme.chapter,
+            )?;
+        }
+
         // rectangle for speed
         engine.draw_rect(
-            Rect::new(0, 0, s_speed.interpolate() as u32, HALF_HEIGHT),
-            COLOR_SPEED,
+            Rect::new(0, SPEED_TOP, s_speed.interpolate() as u32, HALF_HEIGHT),
+            theme.speed,
         )?;
 
         // rectangle for volume
         engine.draw_rect(
-            Rect::new(
-                0,
-                HALF_HEIGHT as i32,
-                s_volume.interpolate() as u32,
-                HALF_HEIGHT,
-            ),
-            COLOR_VOLUME,
+            Rect::new(0, VOLUME_TOP, s_volume.interpolate() as u32, HALF_HEIGHT),
+            theme.volume,
         )?;
 
         // crosshair for speed
+        let speed_origin = speed_origin(&speed_map);
         engine.draw_rect(
             Rect::new(
-                SPEED_ORIGIN - CH_THICKNESS as i32,
-                HALF_HEIGHT as i32 - CH_LENGTH,
+                speed_origin - CH_THICKNESS as i32,
+                VOLUME_TOP - CH_LENGTH,
                 CH_THICKNESS,
                 CH_LENGTH as u32,
             ),
@@ -202,8 +1050,8 @@ fn practically_main() -> Result<(), String> {
         )?;
         engine.draw_rect(
             Rect::new(
-                SPEED_ORIGIN - CH_LENGTH,
-                HALF_HEIGHT as i32 - CH_THICKNESS as i32,
+                speed_origin - CH_LENGTH,
+                VOLUME_TOP - CH_THICKNESS as i32,
                 CH_LENGTH as u32,
                 CH_THICKNESS,
             ),
@@ -211,10 +1059,11 @@ fn practically_main() -> Result<(), String> {
         )?;
 
         // crosshair for volume
+        let volume_origin = volume_origin(volume_law);
         engine.draw_rect(
             Rect::new(
-                VOLUME_ORIGIN - CH_THICKNESS as i32,
-                HALF_HEIGHT as i32,
+                volume_origin - CH_THICKNESS as i32,
+                VOLUME_TOP,
                 CH_THICKNESS,
                 CH_LENGTH as u32,
             ),
@@ -222,16 +1071,133 @@ fn practically_main() -> Result<(), String> {
         )?;
         engine.draw_rect(
             Rect::new(
-                VOLUME_ORIGIN - CH_LENGTH,
-                HALF_HEIGHT as i32,
+                volume_origin - CH_LENGTH,
+                VOLUME_TOP,
                 CH_LENGTH as u32,
                 CH_THICKNESS,
             ),
             WHITE,
         )?;
 
-        engine.draw_surface(surface_speed, (0, 0))?;
-        engine.draw_surface(surface_volume, (0, HALF_HEIGHT as i32))?;
+        engine.draw_surface(surface_speed, (0, SPEED_TOP))?;
+        engine.draw_surface(surface_volume, (0, VOLUME_TOP))?;
+
+        // status lines, right-aligned in the volume bar
+        let mut status = vec![
+            format!("RG: {} ({pre_gain_db:+.1} dB)", gain_mode.name()),
+            format!("vol: {}", volume_law.name()),
+            format!("repeat: {}", queue.repeat.name()),
+            format!(
+                "shuffle: {}",
+                if queue.is_shuffled() { "on" } else { "off" }
+            ),
+            format!(
+                "speed: {} {}-{}x",
+                speed_map.curve.name(),
+                speed_map.min,
+                speed_map.max
+            ),
+            match speed_map.snap {
+                Some(step) => format!("snap: {step}x"),
+                None => "snap: off".to_string(),
+            },
+            format!("night: {}", if night { "on" } else { "off" }),
+            format!("limiter: {}", if limiter { "on" } else { "off" }),
+            format!("xfeed: {}", if crossfeed { "on" } else { "off" }),
+            format!("width: {:.0}%", width * 100.0),
+            format!("karaoke: {}", if karaoke { "on" } else { "off" }),
+        ];
+
+        if let Some(scan) = &scan {
+            status.insert(0, format!("scanning: {} found", scan.progress().0));
+        }
+
+        let lines = status.iter().fold(Vec::<String>::new(), |mut lines, item| {
+            match lines.last_mut() {
+                Some(line) if line.len() + item.len() < STATUS_CHARS => {
+                    line.push_str(" | ");
+                    line.push_str(item);
+                }
+                _ => lines.push(item.clone()),
+            }
+            lines
+        });
+
+        for (row, line) in lines.iter().enumerate() {
+            let surface = small_font.render(line).blended(WHITE).strerr()?;
+            let x = WIDTH as i32 - surface.width() as i32 - 4;
+            let y = VOLUME_TOP + 4 + row as i32 * (SMALL_FONT_SIZE as i32 + 2);
+            engine.draw_surface(surface, (x, y))?;
+        }
+
+        // equalizer panel, covering the speed and volume bars
+        if panel == Some(Panel::Equalizer) {
+            const BAND_WIDTH: u32 = WIDTH / Equalizer::BANDS as u32;
+
+            engine.draw_rect(
+                Rect::new(0, SPEED_TOP, WIDTH, (METER_TOP - SPEED_TOP) as u32),
+                Color::BLACK,
+            )?;
+            engine.draw_rect(Rect::new(0, EQ_CENTER, WIDTH, 1), theme.unplayed)?;
+
+            for (band, (&gain, &freq)) in eq_gains
+                .iter()
+                .zip(Equalizer::FREQUENCIES.iter())
+                .enumerate()
+            {
+                let x = (band as u32 * BAND_WIDTH) as i32;
+                let height = (gain / Equalizer::MAX_GAIN_DB * EQ_RANGE) as i32;
+
+                engine.draw_rect(
+                    Rect::new(
+                        x + 4,
+                        EQ_CENTER - height.max(0),
+                        BAND_WIDTH - 8,
+                        height.unsigned_abs().max(1),
+                    ),
+                    theme.eq,
+                )?;
+
+                let label = if freq >= 1000.0 {
+                    format!("{}k", freq / 1000.0)
+                } else {
+                    format!("{}", freq as u32)
+                };
+                let surface = small_font.render(&label).blended(WHITE).strerr()?;
+                engine.draw_surface(surface, (x + 4, SPEED_TOP))?;
+            }
+
+            let surface = small_font
+                .render(&format!("EQ: {}", eq_preset.map_or("custom", Preset::name)))
+                .blended(WHITE)
+                .strerr()?;
+            engine.draw_surface(surface, (4, METER_TOP - SMALL_FONT_SIZE as i32 - 4))?;
+        }
+
+        // effect chain panel, one row per effect in processing order
+        if panel == Some(Panel::Chain) {
+            engine.draw_rect(
+                Rect::new(0, SPEED_TOP, WIDTH, (METER_TOP - SPEED_TOP) as u32),
+                Color::BLACK,
+            )?;
+
+            for (row, &(name, enabled)) in effects.iter().enumerate() {
+                let y = SPEED_TOP + row as i32 * ROW_HEIGHT;
+                let (color, state) = if enabled {
+                    (theme.eq, "on")
+                } else {
+                    (theme.unplayed, "off")
+                };
+
+                engine.draw_rect(Rect::new(0, y + 2, WIDTH, ROW_HEIGHT as u32 - 4), color)?;
+
+                let surface = small_font
+                    .render(&format!("{}. {name} ({state})", row + 1))
+                    .blended(WHITE)
+                    .strerr()?;
+                engine.draw_surface(surface, (4, y + 4))?;
+            }
+        }
         engine.draw_rect(
             Rect::new(
                 0,
@@ -242,30 +1208,254 @@ fn practically_main() -> Result<(), String> {
             WHITE,
         )?;
 
-        let (go_next, progress) = {
+        let (go_next, progress, levels, chapter) = {
             let mut device = device.lock();
             device.speed.set(to_speed);
             device.volume.set(to_volume);
-            if let Some(y) = scroll {
-                if let Err(e) = device.skip(-y) {
-                    log(Log::Warning, format!("skip failed ({e})"));
-                }
+            device.gain_mode = gain_mode;
+            if let Some(loudness) = loudness.as_mut().and_then(Task::poll) {
+                device.set_loudness(loudness);
+            }
+            pre_gain_db = device.pre_gain_db();
+            if let Some(equalizer) = device.chain.get_mut::<Equalizer>() {
+                equalizer.set_gains(&eq_gains);
+            }
+            if let Some(stereo_width) = device.chain.get_mut::<StereoWidth>() {
+                stereo_width.set_width(width);
+            }
+            effects = device.chain.summary();
+            night = device.chain.is_enabled::<Compressor>();
+            limiter = device.output.is_enabled::<Limiter>();
+            crossfeed = device.chain.is_enabled::<Crossfeed>();
+            karaoke = device.chain.is_enabled::<VocalRemover>();
+            if let Some(y) = scroll
+                && let Err(e) = device.skip(-y)
+            {
+                log(Log::Warning, format!("skip failed ({e})"));
+            }
+            if let Some(progress) = seek
+                && let Err(e) = device.seek(progress)
+            {
+                log(Log::Warning, format!("seek failed ({e})"));
             }
             scroll = None;
-            (device.end, device.progress())
+            seek = None;
+            loop_points = device.loop_points();
+            chapter_marks = device.chapter_marks();
+            (
+                device.end,
+                device.progress(),
+                device.levels.take(),
+                device.chapter().map(str::to_string),
+            )
         };
 
+        if chapter != chapter_shown {
+            engine.set_title(&title(&queue, chapter.as_deref()))?;
+            chapter_shown = chapter;
+        }
+
+        meters.update(levels);
+
+        // level meters, one row per channel, with the clip LED on the right
+        let meter_width = WIDTH - LED_WIDTH;
+        let to_width = |db| (Meters::fraction(db) * meter_width as f32) as u32;
+
+        for (row, channel) in meters.channels.iter().take(2).enumerate() {
+            let y = METER_TOP + (row as u32 * METER_HEIGHT) as i32;
+
+            engine.draw_rect(
+                Rect::new(0, y, to_width(channel.peak), METER_HEIGHT - 1),
+                theme.peak,
+            )?;
+            engine.draw_rect(
+                Rect::new(0, y, to_width(channel.rms), METER_HEIGHT - 1),
+                theme.rms,
+            )?;
+            engine.draw_rect(
+                Rect::new(
+                    to_width(channel.hold) as i32,
+                    y,
+                    CH_THICKNESS,
+                    METER_HEIGHT - 1,
+                ),
+                WHITE,
+            )?;
+        }
+
+        engine.draw_rect(
+            Rect::new(meter_width as i32, METER_TOP, LED_WIDTH, 2 * METER_HEIGHT),
+            if meters.clipping() {
+                theme.clip
+            } else {
+                theme.clip_off
+            },
+        )?;
+
         if progress != progress_prev {
             s_progress.shift_set(progress);
         }
         progress_prev = progress;
 
-        if go_next {
-            device = load(&mut engine, Some(device))?;
+        let current_speed = speed_map.speed(s_speed.target() / WIDTH as f32);
+
+        if go_next || skip || back {
+            if let Some(path) = bookmark_path(&queue) {
+                if go_next {
+                    bookmarks.finish(path);
+                } else if !finished {
+                    remember(&mut bookmarks, path, position(&mut device), current_speed);
+                }
+            }
+
+            if back {
+                queue.retreat();
+            } else {
+                queue.advance(skip);
+            }
+
+            // tracks that cannot be opened any more are passed over, at most once
+            // each so a queue of nothing but broken files still ends, and with
+            // repeat off the queue runs out after its last track
+            let mut passed = 0;
+            // the old track's position is no bookmark for whatever is current on exit
+            finished = true;
+            loop {
+                let Some(track) = queue.current_track() else {
+                    break 'main_loop;
+                };
+
+                match source::Source::open(&track.path) {
+                    Ok(_) => break,
+                    Err(_) if passed >= queue.tracks().len() => break 'main_loop,
+                    Err(e) => {
+                        log(
+                            Log::Warning,
+                            format!("{}: {e}, skipping", track.path.display()),
+                        );
+                        queue.advance(true);
+                        passed += 1;
+                    }
+                }
+            }
+
+            skip = false;
+            back = false;
+            chapter_shown = None;
+            finished = false;
+            (device, waveform, loudness) = load(&mut engine, &queue, Some(device))?;
+
+            match bookmark_path(&queue)
+                .and_then(|path| bookmarks.get(path))
+                .copied()
+            {
+                Some(bookmark) => {
+                    if let Err(e) = device.lock().seek_secs(bookmark.position) {
+                        log(Log::Warning, format!("failed to resume position ({e})"));
+                    }
+                    normal_speed.get_or_insert(current_speed);
+                    s_speed.shift_set(speed_map.fraction(bookmark.speed) * WIDTH as f32);
+                }
+                None => {
+                    if let Some(speed) = normal_speed.take() {
+                        s_speed.shift_set(speed_map.fraction(speed) * WIDTH as f32);
+                    }
+                }
+            }
+        }
+
+        if session_saved.elapsed().as_secs() >= Session::SAVE_SECS
+            && saving.as_ref().is_none_or(JoinHandle::is_finished)
+        {
+            let (position_secs, duration_secs) = position(&mut device);
+
+            if let Some(path) = bookmark_path(&queue)
+                && !finished
+            {
+                remember(
+                    &mut bookmarks,
+                    path,
+                    (position_secs, duration_secs),
+                    current_speed,
+                );
+            }
+
+            let changed = (bookmarks_ok && bookmarks.take_changed()).then(|| bookmarks.clone());
+            // a queue saved mid-scan would resume without the rest of the library
+            let session = scan
+                .is_none()
+                .then(|| {
+                    session(
+                        &queue,
+                        position_secs,
+                        current_speed,
+                        volume_law.gain(s_volume.target() / WIDTH as f32),
+                    )
+                })
+                .filter(|session| last_session.as_ref() != Some(session));
+
+            if let Some(session) = &session {
+                last_session = Some(session.clone());
+            }
+
+            saving = Some(save_on_thread(changed, session));
+            session_saved = Instant::now();
         }
 
         engine.present();
     }
 
+    let current_speed = speed_map.speed(s_speed.target() / WIDTH as f32);
+    let (position_secs, duration_secs) = position(&mut device);
+
+    if let Some(path) = bookmark_path(&queue)
+        && !finished
+    {
+        remember(
+            &mut bookmarks,
+            path,
+            (position_secs, duration_secs),
+            current_speed,
+        );
+    }
+
+    // a bookmarked track's own speed is not the one to start with next time
+    let speed = normal_speed.unwrap_or(current_speed);
+    let volume = volume_law.gain(s_volume.target() / WIDTH as f32);
+
+    if speed != speed_start {
+        config.speed = speed;
+    }
+
+    if volume != volume_start {
+        config.volume = volume;
+    }
+
+    config.volume_law = volume_law;
+    config.speed_map = speed_map;
+    if queue.repeat != repeat_start {
+        config.repeat = queue.repeat;
+    }
+
+    if queue.is_shuffled() != shuffle_start {
+        config.shuffle = queue.is_shuffled();
+    }
+
+    if config_ok && let Err(e) = config.save() {
+        log(Log::Warning, format!("failed to save settings ({e})"));
+    }
+
+    let changed = (bookmarks_ok && bookmarks.take_changed()).then_some(bookmarks);
+    let session = scan
+        .is_none()
+        .then(|| session(&queue, position_secs, current_speed, volume))
+        .filter(|session| last_session.as_ref() != Some(session));
+
+    // the last background save finishes first, so it cannot overwrite this one
+    if let Some(saving) = saving {
+        saving.join().ok();
+    }
+    save_on_thread(changed, session).join().ok();
+
     Ok(())
 }
@@ -0,0 +1,133 @@
+use crate::{dsp, playback::Sample};
+use std::time::Instant;
+
+/// Raw output levels accumulated by the audio callback until the UI takes them.
+pub struct Levels {
+    peak: Vec<Sample>,
+    sum_squares: Vec<f64>,
+    frames: usize,
+    clipped: bool,
+}
+
+impl Levels {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            peak: vec![0.0; channels],
+            sum_squares: vec![0.0; channels],
+            frames: 0,
+            clipped: false,
+        }
+    }
+
+    pub fn feed(&mut self, frame: &[Sample]) {
+        for ((sample, peak), sum_squares) in frame
+            .iter()
+            .zip(self.peak.iter_mut())
+            .zip(self.sum_squares.iter_mut())
+        {
+            let abs = sample.abs();
+            *peak = abs.max(*peak);
+            *sum_squares += (abs * abs) as f64;
+            self.clipped |= abs > 1.0;
+        }
+
+        self.frames += 1;
+    }
+
+    pub fn take(&mut self) -> Self {
+        std::mem::replace(self, Self::new(self.peak.len()))
+    }
+}
+
+pub struct Channel {
+    /// Peak level in dBFS, with instant attack and linear release.
+    pub peak: f32,
+    /// RMS level in dBFS, exponentially smoothed.
+    pub rms: f32,
+    /// Highest recent peak in dBFS.
+    pub hold: f32,
+    hold_age: f32,
+}
+
+impl Channel {
+    const fn new() -> Self {
+        Self {
+            peak: Meters::FLOOR,
+            rms: Meters::FLOOR,
+            hold: Meters::FLOOR,
+            hold_age: 0.0,
+        }
+    }
+}
+
+pub struct Meters {
+    pub channels: Vec<Channel>,
+    clip_age: f32,
+    last_update: Instant,
+}
+
+impl Meters {
+    pub const FLOOR: f32 = -60.0;
+    const RELEASE_DB_PER_SEC: f32 = 24.0;
+    const RMS_TIME_CONSTANT: f32 = 0.3;
+    const HOLD_SECS: f32 = 1.5;
+    const CLIP_SECS: f32 = 2.0;
+
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+            clip_age: Self::CLIP_SECS,
+            last_update: Instant::now(),
+        }
+    }
+
+    fn to_db(amplitude: f64) -> f32 {
+        dsp::to_db(amplitude as f32).max(Self::FLOOR)
+    }
+
+    pub fn update(&mut self, levels: Levels) {
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        self.channels.resize_with(levels.peak.len(), Channel::new);
+
+        let rms_weight = 1.0 - (-dt / Self::RMS_TIME_CONSTANT).exp();
+
+        for ((channel, &peak), &sum_squares) in self
+            .channels
+            .iter_mut()
+            .zip(levels.peak.iter())
+            .zip(levels.sum_squares.iter())
+        {
+            let peak = Self::to_db(peak as f64);
+            channel.peak = peak.max(channel.peak - Self::RELEASE_DB_PER_SEC * dt);
+
+            if levels.frames > 0 {
+                let rms = Self::to_db((sum_squares / levels.frames as f64).sqrt());
+                channel.rms += (rms - channel.rms) * rms_weight;
+            }
+
+            channel.hold_age += dt;
+            if peak >= channel.hold || channel.hold_age > Self::HOLD_SECS {
+                channel.hold = channel.peak;
+                channel.hold_age = 0.0;
+            }
+        }
+
+        self.clip_age = if levels.clipped {
+            0.0
+        } else {
+            self.clip_age + dt
+        };
+    }
+
+    pub fn clipping(&self) -> bool {
+        self.clip_age < Self::CLIP_SECS
+    }
+
+    /// Maps a level in dBFS to `0.0..=1.0`. Levels over full scale stay at the end.
+    pub fn fraction(db: f32) -> f32 {
+        (1.0 - db / Self::FLOOR).clamp(0.0, 1.0)
+    }
+}
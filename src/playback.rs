@@ -1,84 +1,111 @@
 use crate::{
     Smooth, Strerr,
+    chapters::{self, Chapter},
+    dsp::{self, Chain},
+    gain::{GainMode, ReplayGain},
     log::{Log, log},
+    loudness::Loudness,
+    meter::Levels,
+    source::Source,
 };
 use sdl2::audio::AudioCallback;
-use symphonia::core::{
-    audio::{AudioBufferRef, Signal},
-    codecs::{CODEC_TYPE_NULL, Decoder},
-    formats::{FormatReader, SeekMode, SeekTo},
-    io::MediaSourceStream,
-    units::Time,
-};
 
 pub type Sample = f32;
 
+/// State handed over from one track's `Playback` to the next.
+pub struct Carry {
+    buffer: Vec<Sample>,
+    chain: Chain,
+    output: Chain,
+    gain_mode: GainMode,
+}
+
 pub struct Playback {
-    decoder: Box<dyn Decoder>,
-    format: Box<dyn FormatReader>,
-    buffer: Box<[Sample]>,
-    track_id: u32,
+    source: Source,
+    buffer: Vec<Sample>,
     pub sample_rate: u32,
     pub channels: usize,
     offset: usize,
     position: f64,
     total_frames: u64,
+    /// Frames this track starts and stops at, narrower than the file for cue sheet tracks.
+    start: f64,
+    stop: f64,
+    replay_gain: ReplayGain,
+    pub gain_mode: GainMode,
+    pre_gain: Smooth,
     pub speed: Smooth,
     pub volume: Smooth,
+    pub chain: Chain,
+    pub output: Chain,
+    pub levels: Levels,
+    loop_a: Option<f64>,
+    loop_b: Option<f64>,
+    chapters: Vec<Chapter>,
+    /// What followed B when the loop last wrapped, faded out over the seam.
+    seam: Vec<Sample>,
+    seam_pos: usize,
     pub end: bool,
 }
 
 impl Playback {
     const SKIP_SECS: f64 = 5.0;
+    const SEAM_SECS: f64 = 0.01;
+    /// How far back each seek goes when playing in reverse.
+    const REVERSE_BLOCK_SECS: f64 = 1.0;
+    /// Going to the previous chapter this far into one restarts it instead.
+    const CHAPTER_RESTART_SECS: f64 = 3.0;
 
-    pub fn new<P>(path: P, buffer: Option<Box<[Sample]>>) -> Result<Self, String>
+    pub fn new<P>(path: P, carry: Option<Carry>) -> Result<Self, String>
     where
         P: AsRef<std::path::Path>,
     {
-        let src = std::fs::File::open(path).strerr()?;
-        let mss = MediaSourceStream::new(Box::new(src), Default::default());
-        let probed = symphonia::default::get_probe()
-            .format(
-                &Default::default(),
-                mss,
-                &Default::default(),
-                &Default::default(),
-            )
-            .strerr()?;
-
-        let format = probed.format;
-
-        let track = format
-            .tracks()
-            .iter()
-            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
-            .ok_or("no supported audio tracks")?;
-
-        let sample_rate = track.codec_params.sample_rate.ok_or("no sample rate")?;
-        let channels = track.codec_params.channels.ok_or("no channels")?.count();
-        let total_frames = track.codec_params.n_frames.ok_or("no frames")?;
-
-        let decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &Default::default())
-            .strerr()?;
-
-        let track_id = track.id;
+        let path = path.as_ref();
+        let source = Source::open(path)?;
+        let chapters = chapters::read(path).unwrap_or_else(|e| {
+            log(Log::Warning, format!("failed to read chapters ({e})"));
+            Vec::new()
+        });
+        let sample_rate = source.sample_rate;
+        let channels = source.channels;
+        let total_frames = source.total_frames;
+        let replay_gain = source.replay_gain;
+        let (mut buffer, mut chain, mut output, gain_mode) = match carry {
+            Some(carry) => (carry.buffer, carry.chain, carry.output, carry.gain_mode),
+            None => (
+                Vec::new(),
+                Chain::standard(),
+                Chain::output(),
+                GainMode::Track,
+            ),
+        };
+        buffer.clear();
+        chain.reset();
+        output.reset();
 
         Ok(Playback {
-            decoder,
-            format,
-            buffer: match buffer {
-                Some(buffer) => buffer,
-                None => Box::new([]),
-            },
-            track_id,
+            source,
+            buffer,
             sample_rate,
             channels,
             offset: 0,
             position: 0.0,
             total_frames,
+            start: 0.0,
+            stop: total_frames as f64,
+            replay_gain,
+            gain_mode,
+            pre_gain: Smooth::new(replay_gain.factor(gain_mode)),
             speed: Smooth::new(1.0),
             volume: Smooth::new(0.25),
+            chain,
+            output,
+            levels: Levels::new(channels),
+            loop_a: None,
+            loop_b: None,
+            chapters,
+            seam: Vec::new(),
+            seam_pos: 0,
             end: false,
         })
     }
@@ -88,7 +115,8 @@ impl Playback {
             self.refill()?;
             self.get(index)
         } else if index < self.offset {
-            Err("index < offset").strerr()
+            self.refill_backward(index)?;
+            self.get(index)
         } else {
             self.buffer
                 .get(index - self.offset)
@@ -103,94 +131,219 @@ impl Playback {
     }
 
     fn refill(&mut self) -> Result<(), String> {
-        type SymErr = symphonia::core::errors::Error;
+        self.offset += self.buffer.len();
+        self.buffer.clear();
+        self.source.decode_next(&mut self.buffer).map(|_| ())
+    }
 
-        loop {
-            let packet = self.format.next_packet().strerr()?;
+    /// Seeks to a block ending at `index` and decodes it forward, for reverse playback.
+    /// The reader is left right after the block, so `refill` keeps working afterwards.
+    fn refill_backward(&mut self, index: usize) -> Result<(), String> {
+        let block = (Self::REVERSE_BLOCK_SECS * self.sample_rate as f64) as u64;
+        let frame = (index / self.channels) as u64;
 
-            while !self.format.metadata().is_latest() {
-                self.format.metadata().pop();
-            }
+        self.buffer.clear();
+        self.offset = self.source.seek(frame.saturating_sub(block))? as usize * self.channels;
 
-            if packet.track_id() != self.track_id {
-                continue;
-            }
+        if index < self.offset {
+            return Err("index < offset").strerr();
+        }
 
-            match self.decoder.decode(&packet) {
-                Ok(decoded) => {
-                    let spec = *decoded.spec();
-                    let frames = decoded.frames();
-                    let channels = spec.channels.count();
-                    let samples = frames * channels;
-
-                    let cow = match decoded {
-                        AudioBufferRef::F32(cow) => cow,
-                        _ => return Err("shit").strerr(),
-                    };
-
-                    self.offset += self.buffer.len();
-
-                    if self.buffer.len() != samples {
-                        log(
-                            Log::Info,
-                            format!("buffer size changed ({} -> {})", self.buffer.len(), samples),
-                        );
-
-                        self.buffer = (0..samples)
-                            .map(|_| 0.0)
-                            .collect::<Vec<Sample>>()
-                            .into_boxed_slice();
-                    }
-
-                    for frame in 0..frames {
-                        for channel in 0..channels {
-                            self.buffer[frame * channels + channel] = cow.chan(channel)[frame];
-                        }
-                    }
-
-                    return Ok(());
-                }
-                Err(SymErr::IoError(e)) => log(Log::Warning, format!("IoError says \"{e}\"")),
-                Err(SymErr::DecodeError(e)) => {
-                    log(Log::Warning, format!("DecodeError says \"{e}\""))
-                }
-                Err(e) => {
-                    return Err(e).strerr();
-                }
-            }
+        while self.needs_refill(index) {
+            self.source.decode_next(&mut self.buffer)?;
         }
+
+        Ok(())
+    }
+
+    /// Plays only `start` to `end` seconds of the file, for a cue sheet track.
+    pub fn set_bounds(&mut self, (start, end): (f64, Option<f64>)) -> Result<(), String> {
+        let rate = self.sample_rate as f64;
+        let total = self.total_frames as f64;
+
+        self.start = (start * rate).clamp(0.0, total);
+        self.stop = end.map_or(total, |end| (end * rate).clamp(self.start, total));
+        self.seek_frame(self.start)
+    }
+
+    fn length(&self) -> f64 {
+        (self.stop - self.start).max(1.0)
     }
 
     pub const fn progress(&self) -> f32 {
-        (self.position / self.total_frames as f64) as f32
+        ((self.position - self.start) / (self.stop - self.start).max(1.0)) as f32
     }
 
-    pub fn take_buffer(self) -> Box<[Sample]> {
-        self.buffer
+    pub fn into_carry(self) -> Carry {
+        Carry {
+            buffer: self.buffer,
+            chain: self.chain,
+            output: self.output,
+            gain_mode: self.gain_mode,
+        }
     }
 
-    fn skip_impl(&mut self, seconds: f64) -> Result<(), String> {
-        self.position = (self.position + seconds * self.sample_rate as f64).max(0.0);
-        self.offset = self.position as usize * self.channels;
-        let seconds = (self.position / self.sample_rate as f64) as u64;
+    pub fn is_tagged(&self) -> bool {
+        self.replay_gain.is_tagged()
+    }
 
-        self.format
-            .seek(
-                SeekMode::Coarse,
-                SeekTo::Time {
-                    time: Time::new(seconds, 0.0),
-                    track_id: Some(self.track_id),
-                },
-            )
-            .strerr()?;
+    /// Uses a loudness measurement in place of missing tags.
+    pub fn set_loudness(&mut self, loudness: &Loudness) {
+        self.replay_gain = ReplayGain::from_loudness(loudness);
+    }
+
+    /// The pre-gain currently applied from ReplayGain/R128 tags, in dB.
+    pub fn pre_gain_db(&self) -> f32 {
+        dsp::to_db(self.replay_gain.factor(self.gain_mode))
+    }
 
-        self.decoder.reset();
+    fn seek_frame(&mut self, frame: f64) -> Result<(), String> {
+        self.position = frame.clamp(self.start, self.stop);
+        self.buffer.clear();
+        self.offset = self.source.seek(self.position as u64)? as usize * self.channels;
 
         Ok(())
     }
 
+    /// Seconds into the track, which for a cue sheet track is not the file.
+    pub fn position_secs(&self) -> f64 {
+        (self.position - self.start) / self.sample_rate as f64
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        (self.stop - self.start) / self.sample_rate as f64
+    }
+
+    pub fn seek_secs(&mut self, secs: f64) -> Result<(), String> {
+        self.seek_frame(self.start + secs * self.sample_rate as f64)
+    }
+
+    pub fn seek(&mut self, progress: f32) -> Result<(), String> {
+        self.seek_frame(self.start + progress as f64 * self.length())
+    }
+
     pub fn skip(&mut self, mult: i32) -> Result<(), String> {
-        self.skip_impl(Self::SKIP_SECS * mult as f64)
+        self.seek_frame(self.position + Self::SKIP_SECS * mult as f64 * self.sample_rate as f64)
+    }
+
+    pub fn set_loop_a(&mut self, progress: f32) {
+        self.loop_a = Some(self.start + progress as f64 * self.length());
+    }
+
+    pub fn set_loop_b(&mut self, progress: f32) {
+        self.loop_b = Some(self.start + progress as f64 * self.length());
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_a = None;
+        self.loop_b = None;
+    }
+
+    /// A and B as progress, for drawing.
+    pub fn loop_points(&self) -> (Option<f32>, Option<f32>) {
+        let progress = |frame: f64| ((frame - self.start) / self.length()) as f32;
+        (self.loop_a.map(progress), self.loop_b.map(progress))
+    }
+
+    /// Chapter starts within this track, in frames.
+    fn chapter_frames(&self) -> impl Iterator<Item = (f64, &Chapter)> {
+        let rate = self.sample_rate as f64;
+        self.chapters
+            .iter()
+            .map(move |chapter| (chapter.start * rate, chapter))
+            .filter(|&(frame, _)| (self.start..self.stop).contains(&frame))
+    }
+
+    /// Chapter starts as progress, for drawing.
+    pub fn chapter_marks(&self) -> Vec<f32> {
+        self.chapter_frames()
+            .map(|(frame, _)| ((frame - self.start) / self.length()) as f32)
+            .collect()
+    }
+
+    /// Title of the chapter at the playhead.
+    pub fn chapter(&self) -> Option<&str> {
+        self.chapter_frames()
+            .filter(|&(frame, _)| frame <= self.position)
+            .last()
+            .map(|(_, chapter)| chapter.title.as_str())
+    }
+
+    pub fn next_chapter(&mut self) -> Result<(), String> {
+        let next = self
+            .chapter_frames()
+            .map(|(frame, _)| frame)
+            .find(|&frame| frame > self.position);
+
+        match next {
+            Some(frame) => self.seek_frame(frame),
+            None => Ok(()),
+        }
+    }
+
+    /// Back to the start of the current chapter, or to the one before if it just started.
+    pub fn previous_chapter(&mut self) -> Result<(), String> {
+        let restart = self.position - Self::CHAPTER_RESTART_SECS * self.sample_rate as f64;
+        let previous = self
+            .chapter_frames()
+            .map(|(frame, _)| frame)
+            .filter(|&frame| frame <= restart)
+            .last();
+
+        self.seek_frame(previous.unwrap_or(self.start))
+    }
+
+    fn loop_region(&self) -> Option<(f64, f64)> {
+        self.loop_a.zip(self.loop_b).filter(|(a, b)| a < b)
+    }
+
+    /// Jumps back to A once the playhead crosses B (or to B once it crosses A in
+    /// reverse), keeping what would have followed so the seam can be crossfaded.
+    fn wrap_loop(&mut self, speed: f32) {
+        let Some((a, b)) = self.loop_region() else {
+            return;
+        };
+
+        let (edge, to) = if speed >= 0.0 && self.position >= b {
+            (b, a + (self.position - b))
+        } else if speed < 0.0 && self.position < a {
+            (a, b - (a - self.position))
+        } else {
+            return;
+        };
+
+        let seam_frames = (Self::SEAM_SECS * self.sample_rate as f64) as isize;
+        let step = if speed >= 0.0 { 1 } else { -1 };
+        let edge = edge as isize;
+
+        self.seam.clear();
+        for frame in (0..seam_frames).map(|i| edge + i * step) {
+            for channel in 0..self.channels {
+                let index = (frame.max(0) as usize) * self.channels + channel;
+                let sample = self.get(index).unwrap_or_default();
+                self.seam.push(sample);
+            }
+        }
+        self.seam_pos = 0;
+
+        if let Err(e) = self.seek_frame(to) {
+            log(Log::Warning, format!("loop failed ({e})"));
+        }
+    }
+
+    fn fade_seam(&mut self, samples: &mut [Sample]) {
+        let frames = self.seam.len() / self.channels;
+
+        if self.seam_pos < frames {
+            let t = (self.seam_pos + 1) as f32 / (frames + 1) as f32;
+            let tail = &self.seam[self.seam_pos * self.channels..];
+
+            for (sample, &tail) in samples.iter_mut().zip(tail) {
+                *sample = t * *sample + (1.0 - t) * tail;
+            }
+
+            self.seam_pos += 1;
+        }
     }
 }
 
@@ -205,23 +358,63 @@ impl AudioCallback for Playback {
             log(Log::Warning, "nasty ahh buffer length");
         }
 
+        self.pre_gain.set(self.replay_gain.factor(self.gain_mode));
+
         for frame in 0..frames {
-            let index = (self.position as usize) * channels;
             let t = frame as f32 / frames as f32;
-            self.position += self.speed.interpolate(t) as f64;
+            let speed = self.speed.interpolate(t);
+            self.wrap_loop(speed);
+
+            let index = (self.position as usize) * channels;
+            // reversing into the start waits there in silence instead of holding
+            // the first sample, so playing forward again picks up from the start
+            let at_start = speed < 0.0 && self.position <= self.start;
+            self.position = (self.position + speed as f64).max(self.start);
+
+            // the next cue sheet track may go on in the same file, so this one ends at `stop`
+            let past_stop = index >= self.stop as usize * channels;
+            self.end |= past_stop;
 
             for channel in 0..channels {
-                out[frame * channels + channel] = self.get(index + channel).unwrap_or_else(|e| {
-                    if !self.end {
-                        log(Log::Warning, e);
-                    }
-
-                    self.end = true;
-                    0.0
-                }) * self.volume.interpolate(t);
+                out[frame * channels + channel] = match past_stop || at_start {
+                    true => 0.0,
+                    false => self.get(index + channel).unwrap_or_else(|e| {
+                        if !self.end {
+                            log(Log::Warning, e);
+                        }
+
+                        self.end = true;
+                        0.0
+                    }),
+                };
+            }
+
+            let samples = &mut out[frame * channels..(frame + 1) * channels];
+            self.fade_seam(samples);
+
+            let pre_gain = self.pre_gain.interpolate(t);
+            for sample in samples.iter_mut() {
+                *sample *= pre_gain;
             }
         }
 
+        let out = &mut out[..frames * channels];
+        self.chain.process(out, self.sample_rate, channels);
+
+        for (frame, samples) in out.chunks_exact_mut(channels).enumerate() {
+            let volume = self.volume.interpolate(frame as f32 / frames as f32);
+
+            for sample in samples.iter_mut() {
+                *sample *= volume;
+            }
+
+            // metered before the limiter, so the clip LED still shows what it catches
+            self.levels.feed(samples);
+        }
+
+        self.output.process(out, self.sample_rate, channels);
+
+        self.pre_gain.stabilize();
         self.speed.stabilize();
         self.volume.stabilize();
     }
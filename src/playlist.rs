@@ -0,0 +1,294 @@
+use crate::{
+    dirs,
+    log::{Log, log},
+    queue::Track,
+    strerr::Strerr,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const EXTENSIONS: [&str; 4] = ["m3u", "m3u8", "pls", "xspf"];
+
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Reads the tracks of an M3U/M3U8, PLS or XSPF playlist. Relative paths are
+/// resolved against the playlist's folder, and remote entries and ones that
+/// are not files are skipped.
+pub fn load(path: &Path) -> Result<Vec<Track>, String> {
+    let bytes = std::fs::read(path).strerr()?;
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{FEFF}');
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let entries = match ext.as_str() {
+        "pls" => parse_pls(text),
+        "xspf" => parse_xspf(text),
+        _ => parse_m3u(text),
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|(location, title)| {
+            let track = resolve(base, &location)?;
+
+            // a playlist outlives the files it lists, and folders are not tracks
+            if !track.is_file() {
+                log(
+                    Log::Warning,
+                    format!(
+                        "{}: {} is not a file, skipping",
+                        path.display(),
+                        track.display()
+                    ),
+                );
+                return None;
+            }
+
+            Some(Track {
+                title,
+                ..Track::new(track)
+            })
+        })
+        .collect())
+}
+
+fn resolve(base: &Path, location: &str) -> Option<PathBuf> {
+    let path = match location.strip_prefix("file://") {
+        Some(uri) => PathBuf::from(percent_decode(uri.strip_prefix("localhost").unwrap_or(uri))),
+        None if location.contains("://") => return None,
+        None => PathBuf::from(location),
+    };
+
+    Some(base.join(path))
+}
+
+/// `#EXTINF:<seconds>,<title>` names the path on the next line.
+fn parse_m3u(text: &str) -> Vec<(String, Option<String>)> {
+    let mut entries = Vec::new();
+    let mut title = None;
+
+    for line in text.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            title = info
+                .split_once(',')
+                .map(|(_, title)| title.trim().to_string())
+                .filter(|title| !title.is_empty());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entries.push((line.to_string(), title.take()));
+        }
+    }
+
+    entries
+}
+
+/// `FileN=` and `TitleN=` keys, in the order of N.
+fn parse_pls(text: &str) -> Vec<(String, Option<String>)> {
+    let mut entries: BTreeMap<u32, (Option<String>, Option<String>)> = BTreeMap::new();
+
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim().to_string();
+
+        if let Some(n) = key.strip_prefix("file").and_then(|n| n.parse().ok()) {
+            entries.entry(n).or_default().0 = Some(value);
+        } else if let Some(n) = key.strip_prefix("title").and_then(|n| n.parse().ok()) {
+            entries.entry(n).or_default().1 = Some(value);
+        }
+    }
+
+    entries
+        .into_values()
+        .filter_map(|(file, title)| Some((file?, title)))
+        .collect()
+}
+
+/// `<location>` and `<title>` of every `<track>`. Only as much XML as XSPF needs.
+fn parse_xspf(text: &str) -> Vec<(String, Option<String>)> {
+    elements(text, "track")
+        .filter_map(|track| {
+            let location = elements(track, "location").next()?;
+            let title = elements(track, "title").next().map(unescape);
+            Some((unescape(location), title))
+        })
+        .collect()
+}
+
+/// Contents of each `<tag>` or `<tag ...>` element, not descending into nested ones of the same name.
+fn elements<'a>(xml: &'a str, tag: &'a str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut rest = xml;
+
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find(&open)?;
+            let after = &rest[start + open.len()..];
+
+            // `<tracklist>` starts with `<track` too
+            if !after.starts_with(['>', ' ', '\t', '\r', '\n']) {
+                rest = after;
+                continue;
+            }
+
+            let body = &after[after.find('>')? + 1..];
+            let end = body.find(&close)?;
+            rest = &body[end + close.len()..];
+
+            return Some(body[..end].trim());
+        }
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Writes `tracks` as an extended M3U8 playlist with absolute paths. Cue sheet
+/// tracks are left out, as a playlist entry cannot hold where they start and end.
+pub fn save_m3u8(path: &Path, tracks: &[&Track]) -> Result<(), String> {
+    let mut text = String::from("#EXTM3U\n");
+    let skipped = tracks.iter().filter(|track| track.is_cue_track()).count();
+
+    if skipped > 0 {
+        log(
+            Log::Warning,
+            format!("{skipped} cue sheet track(s) left out of the playlist"),
+        );
+    }
+
+    for track in tracks.iter().filter(|track| !track.is_cue_track()) {
+        let title = track.title.clone().unwrap_or_else(|| {
+            track
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let location = std::fs::canonicalize(&track.path).unwrap_or_else(|_| track.path.clone());
+
+        text.push_str(&format!("#EXTINF:-1,{title}\n{}\n", location.display()));
+    }
+
+    dirs::write_atomic(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(location: &str, title: Option<&str>) -> (String, Option<String>) {
+        (location.to_string(), title.map(str::to_string))
+    }
+
+    #[test]
+    fn m3u_titles_name_the_next_path() {
+        let text = "#EXTM3U\n#EXTINF:123,Artist - Song\nsong.mp3\n\n# comment\nother.mp3\n#EXTINF:-1,\nlast.mp3\n";
+
+        assert_eq!(
+            parse_m3u(text),
+            [
+                entry("song.mp3", Some("Artist - Song")),
+                entry("other.mp3", None),
+                entry("last.mp3", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn pls_follows_entry_numbers() {
+        let text = "[playlist]\nFile2=b.mp3\nTitle1=First\nfile1=a.mp3\nTitle3=No file\nNumberOfEntries=2\n";
+
+        assert_eq!(
+            parse_pls(text),
+            [entry("a.mp3", Some("First")), entry("b.mp3", None)]
+        );
+    }
+
+    #[test]
+    fn xspf_reads_tracks_not_the_tracklist() {
+        let text = r#"<playlist><trackList>
+            <track><title>Rock &amp; Roll</title><location>file:///music/a%20b.mp3</location></track>
+            <track attr="x">
+                <location>c.mp3</location>
+            </track>
+            <track><title>No location</title></track>
+        </trackList></playlist>"#;
+
+        assert_eq!(
+            parse_xspf(text),
+            [
+                entry("file:///music/a%20b.mp3", Some("Rock & Roll")),
+                entry("c.mp3", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b"), "a b");
+        assert_eq!(percent_decode("%C3%A9t%C3%A9"), "été");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn remote_entries_are_skipped() {
+        let base = Path::new("/lists");
+
+        assert_eq!(
+            resolve(base, "file:///music/a%20b.mp3"),
+            Some(PathBuf::from("/music/a b.mp3"))
+        );
+        assert_eq!(
+            resolve(base, "rel/c.mp3"),
+            Some(PathBuf::from("/lists/rel/c.mp3"))
+        );
+        assert_eq!(resolve(base, "http://example.com/x.mp3"), None);
+    }
+}
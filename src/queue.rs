@@ -0,0 +1,232 @@
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    #[default]
+    Off,
+    All,
+    One,
+}
+
+impl Repeat {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::All => "all",
+            Self::One => "one",
+        }
+    }
+
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub path: PathBuf,
+    /// Title from a playlist or cue sheet, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Seconds into `path` where a cue sheet track starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+    /// Seconds into `path` where a cue sheet track ends, or the end of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+impl Track {
+    pub const fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            title: None,
+            start: None,
+            end: None,
+        }
+    }
+
+    /// Whether this is only part of its file, sharing it with other tracks.
+    pub const fn is_cue_track(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// Start and end in seconds, for the whole file if not a cue sheet track.
+    pub fn span(&self) -> (f64, Option<f64>) {
+        (self.start.unwrap_or(0.0), self.end)
+    }
+}
+
+/// Files in play order. The original order is kept so shuffling can be undone.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Queue {
+    files: Vec<Track>,
+    /// Indices into `files`, in play order.
+    order: Vec<usize>,
+    cursor: usize,
+    shuffle: bool,
+    pub repeat: Repeat,
+}
+
+impl Queue {
+    /// A `seed` makes the initial shuffle reproducible.
+    pub fn new(files: Vec<Track>, shuffle: bool, seed: Option<u64>, repeat: Repeat) -> Self {
+        let mut order: Vec<usize> = (0..files.len()).collect();
+
+        if shuffle {
+            match seed {
+                Some(seed) => order.shuffle(&mut StdRng::seed_from_u64(seed)),
+                None => order.shuffle(&mut rand::rng()),
+            }
+        }
+
+        Self {
+            files,
+            order,
+            cursor: 0,
+            shuffle,
+            repeat,
+        }
+    }
+
+    /// Whether `order` is a permutation of `files`, for queues read back from disk.
+    pub fn is_consistent(&self) -> bool {
+        let mut seen = vec![false; self.files.len()];
+
+        self.order.len() == self.files.len()
+            && self
+                .order
+                .iter()
+                .all(|&index| index < seen.len() && !std::mem::replace(&mut seen[index], true))
+    }
+
+    pub fn current_track(&self) -> Option<&Track> {
+        self.order.get(self.cursor).map(|&index| &self.files[index])
+    }
+
+    /// Every track in play order, including those already played.
+    pub fn tracks(&self) -> Vec<&Track> {
+        self.order.iter().map(|&index| &self.files[index]).collect()
+    }
+
+    /// Number of tracks after the current one.
+    pub fn remaining(&self) -> usize {
+        self.order.len().saturating_sub(self.cursor + 1)
+    }
+
+    /// Moves to the next track. Only a track ending on its own (`manual` unset)
+    /// honours `Repeat::One`.
+    pub fn advance(&mut self, manual: bool) {
+        if self.repeat == Repeat::One && !manual {
+            return;
+        }
+
+        self.cursor += 1;
+
+        if self.cursor >= self.order.len() && self.repeat != Repeat::Off {
+            self.cursor = 0;
+
+            if self.shuffle {
+                self.order.shuffle(&mut rand::rng());
+            }
+        }
+    }
+
+    /// Moves to the previous track, wrapping around to the last one with `Repeat::All`.
+    pub fn retreat(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        } else if self.repeat == Repeat::All {
+            self.cursor = self.order.len().saturating_sub(1);
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Adds tracks found after the queue was made. Shuffled, each lands at a random
+    /// place among the upcoming tracks, or anywhere if the queue was empty.
+    pub fn extend(&mut self, tracks: Vec<Track>) {
+        let mut rng = rand::rng();
+        // nothing has played yet, so the first track is up for grabs too
+        let first = match self.order.is_empty() {
+            true => self.cursor,
+            false => self.cursor + 1,
+        };
+
+        for track in tracks {
+            let last = self.order.len();
+            self.order.push(self.files.len());
+            self.files.push(track);
+
+            // an inside-out Fisher-Yates step, so the upcoming tracks stay evenly shuffled
+            if self.shuffle {
+                let start = first.min(last);
+                self.order.swap(last, rng.random_range(start..=last));
+            }
+        }
+    }
+
+    /// Puts the tracks in the order of `keys`, given in the order the tracks were
+    /// added, for a scan that found them out of order. The current track keeps
+    /// playing, and unshuffled, the ones after it follow the new order.
+    pub fn sort_files<K: Ord>(&mut self, keys: &[K]) {
+        if keys.len() != self.files.len() {
+            return;
+        }
+
+        let mut sorted: Vec<usize> = (0..keys.len()).collect();
+        sorted.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+
+        let mut rank = vec![0; sorted.len()];
+        for (new, &old) in sorted.iter().enumerate() {
+            rank[old] = new;
+        }
+
+        let mut files: Vec<_> = std::mem::take(&mut self.files)
+            .into_iter()
+            .zip(&rank)
+            .collect();
+        files.sort_by_key(|&(_, &rank)| rank);
+        self.files = files.into_iter().map(|(track, _)| track).collect();
+
+        for index in &mut self.order {
+            *index = rank[*index];
+        }
+
+        if !self.shuffle
+            && let Some(rest) = self.order.get_mut(self.cursor + 1..)
+        {
+            rest.sort_unstable();
+        }
+    }
+
+    pub const fn is_shuffled(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Shuffles the tracks after the current one, or goes back to the original
+    /// order from the current track on. Either way the current track keeps playing.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+
+        if shuffle {
+            if let Some(rest) = self.order.get_mut(self.cursor + 1..) {
+                rest.shuffle(&mut rand::rng());
+            }
+        } else {
+            if let Some(&current) = self.order.get(self.cursor) {
+                self.cursor = current;
+            }
+            self.order = (0..self.files.len()).collect();
+        }
+    }
+}
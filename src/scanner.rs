@@ -0,0 +1,769 @@
+use crate::{
+    cue, dirs,
+    log::{Log, log},
+    playlist,
+    queue::Track,
+    strerr::Strerr,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    time::UNIX_EPOCH,
+};
+
+/// Identifies a folder however it was reached, so symlinks cannot lead into a loop.
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &Metadata) -> Option<DirId> {
+    path.canonicalize().ok()
+}
+
+/// Seconds and nanoseconds, which change whenever an entry is added, removed or renamed.
+type Mtime = (u64, u32);
+
+fn mtime(metadata: &Metadata) -> Option<Mtime> {
+    let since = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((since.as_secs(), since.subsec_nanos()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A pattern of `*` (within a folder), `**` (across folders) and `?`. Without a
+/// `/` it matches names, otherwise paths relative to the folder being scanned.
+struct Glob {
+    pattern: Vec<char>,
+    is_path: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+            is_path: pattern.contains('/'),
+        }
+    }
+
+    fn matches(&self, name: &str, relative: &str) -> bool {
+        let text: Vec<char> = match self.is_path {
+            true => relative.chars().collect(),
+            false => name.chars().collect(),
+        };
+
+        glob_match(&self.pattern, &text)
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // `**/` also matches no folders at all
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        ['?', rest @ ..] => text
+            .split_first()
+            .is_some_and(|(&c, text)| c != '/' && glob_match(rest, text)),
+        [c, rest @ ..] => text
+            .split_first()
+            .is_some_and(|(t, text)| t == c && glob_match(rest, text)),
+    }
+}
+
+/// What a scan passed over, reported once it is done.
+#[derive(Default)]
+pub struct Summary {
+    pub hidden: usize,
+    pub excluded: usize,
+    /// Folders reached again through a symlink, and not entered a second time.
+    pub loops: Vec<PathBuf>,
+    pub unreadable: Vec<(PathBuf, String)>,
+}
+
+impl Summary {
+    fn append(&mut self, mut other: Self) {
+        self.hidden += other.hidden;
+        self.excluded += other.excluded;
+        self.loops.append(&mut other.loops);
+        self.unreadable.append(&mut other.unreadable);
+    }
+
+    pub fn report(&self) {
+        if self.hidden > 0 {
+            log(
+                Log::Info,
+                format!("skipped {} hidden file(s)/folder(s)", self.hidden),
+            );
+        }
+
+        if self.excluded > 0 {
+            log(
+                Log::Info,
+                format!("skipped {} excluded file(s)/folder(s)", self.excluded),
+            );
+        }
+
+        for path in &self.loops {
+            log(
+                Log::Warning,
+                format!(
+                    "{}: already scanned, not following the link again",
+                    path.display()
+                ),
+            );
+        }
+
+        for (path, e) in &self.unreadable {
+            log(Log::Warning, format!("{}: {e}", path.display()));
+        }
+
+        if !self.unreadable.is_empty() {
+            log(
+                Log::Warning,
+                format!("{} path(s) could not be read", self.unreadable.len()),
+            );
+        }
+    }
+}
+
+/// Which files in a folder are collected.
+pub struct Scanner {
+    /// Lowercase, without the dot.
+    extensions: Vec<String>,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    /// Whether names starting with `.` are scanned.
+    hidden: bool,
+}
+
+impl Scanner {
+    pub fn new(
+        extensions: &[String],
+        include: &[String],
+        exclude: &[String],
+        hidden: bool,
+    ) -> Self {
+        Self {
+            extensions: extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                // cue sheets are always picked up, to split the files they describe
+                .chain(["cue".to_string()])
+                .collect(),
+            include: include.iter().map(|pattern| Glob::new(pattern)).collect(),
+            exclude: exclude.iter().map(|pattern| Glob::new(pattern)).collect(),
+            hidden,
+        }
+    }
+
+    /// Whether `path` under `root` is looked at at all, counting what is not.
+    fn admits(&self, root: &Path, path: &Path, summary: &mut Summary) -> bool {
+        let (name, relative) = names(root, path);
+
+        if !self.hidden && name.starts_with('.') {
+            summary.hidden += 1;
+            false
+        } else if self
+            .exclude
+            .iter()
+            .any(|glob| glob.matches(&name, &relative))
+        {
+            summary.excluded += 1;
+            false
+        } else {
+            true
+        }
+    }
+
+    fn wants(&self, root: &Path, path: &Path) -> bool {
+        let (name, relative) = names(root, path);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        extension.is_some_and(|extension| self.extensions.contains(&extension))
+            && (self.include.is_empty()
+                || self
+                    .include
+                    .iter()
+                    .any(|glob| glob.matches(&name, &relative)))
+    }
+}
+
+/// The name of `path` and its path below `root`, `/`-separated for globs.
+fn names(root: &Path, path: &Path) -> (String, String) {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let relative = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    (name, relative)
+}
+
+/// The entries of a folder as of `mtime`, sorted by name.
+#[derive(Clone, Serialize, Deserialize)]
+struct Listing {
+    mtime: Mtime,
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Listing {
+    /// Symlinks are followed to tell folders from files, so a broken one is unreadable.
+    fn read(dir: &Path, mtime: Mtime, summary: &mut Summary) -> Result<Self, String> {
+        let mut listing = Self {
+            mtime,
+            dirs: Vec::new(),
+            files: Vec::new(),
+        };
+
+        for entry in std::fs::read_dir(dir).strerr()? {
+            let entry = entry.strerr()?;
+            let name = PathBuf::from(entry.file_name());
+            let is_dir = match entry.file_type().strerr()? {
+                kind if kind.is_symlink() => match std::fs::metadata(entry.path()) {
+                    Ok(metadata) => metadata.is_dir(),
+                    Err(e) => {
+                        summary.unreadable.push((entry.path(), e.to_string()));
+                        continue;
+                    }
+                },
+                kind => kind.is_dir(),
+            };
+
+            match is_dir {
+                true => listing.dirs.push(name),
+                false => listing.files.push(name),
+            }
+        }
+
+        listing.dirs.sort();
+        listing.files.sort();
+
+        Ok(listing)
+    }
+
+    /// Names that are not UTF-8 cannot be written to the index.
+    fn is_storable(&self) -> bool {
+        self.dirs
+            .iter()
+            .chain(&self.files)
+            .all(|name| name.to_str().is_some())
+    }
+}
+
+/// Folder listings from the last scans, kept in the cache directory. A folder
+/// whose modification time is unchanged is not read again, only its subfolders
+/// are checked.
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    dirs: BTreeMap<PathBuf, Listing>,
+}
+
+impl Index {
+    fn path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("index.toml"))
+    }
+
+    fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        Ok(dirs::read_toml(&path)?.unwrap_or_default())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no cache directory")?;
+        let text = toml::to_string(self).strerr()?;
+
+        dirs::write_atomic(&path, text)
+    }
+
+    fn get(&self, dir: &Path, mtime: Option<Mtime>) -> Option<&Listing> {
+        let key = std::path::absolute(dir).ok()?;
+        self.dirs
+            .get(&key)
+            .filter(|listing| Some(listing.mtime) == mtime)
+    }
+
+    /// Replaces everything under `roots` with `listings`, so removed folders do not linger.
+    fn update(&mut self, roots: &[PathBuf], listings: BTreeMap<PathBuf, Listing>) {
+        let roots: Vec<_> = roots
+            .iter()
+            .filter_map(|root| std::path::absolute(root).ok())
+            .collect();
+
+        self.dirs
+            .retain(|dir, _| !roots.iter().any(|root| dir.starts_with(root)));
+        self.dirs.extend(listings);
+    }
+}
+
+/// Where a found track goes once the scan is done: roots in the order given, and
+/// within a folder by path. Tracks of a file or playlist root keep their order.
+pub type Rank = (usize, PathBuf);
+
+/// Puts what a scan found in rank order.
+pub fn into_order(mut found: Vec<(Rank, Track)>) -> Vec<Track> {
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    found.into_iter().map(|(_, track)| track).collect()
+}
+
+/// Replaces the files split by cue sheets among `paths` with the sheets' tracks.
+fn split_by_cue_sheets(paths: Vec<PathBuf>) -> Vec<Track> {
+    let mut split = HashSet::new();
+    let mut tracks = Vec::new();
+
+    for path in paths {
+        if !cue::is_cue(&path) {
+            tracks.push(Track::new(path));
+            continue;
+        }
+
+        match cue::load(&path) {
+            Ok(sheet) => {
+                split.extend(sheet.iter().map(|track| track.path.clone()));
+                tracks.extend(sheet);
+            }
+            Err(e) => log(Log::Warning, format!("{}: {e}", path.display())),
+        }
+    }
+
+    tracks.retain(|track| track.is_cue_track() || !split.contains(&track.path));
+    tracks
+}
+
+/// A root that is a file: a cue sheet, a playlist or a track.
+fn expand(path: &Path) -> Result<Vec<Track>, String> {
+    if cue::is_cue(path) {
+        cue::load(path)
+    } else if playlist::is_playlist(path) {
+        playlist::load(path)
+    } else {
+        Ok(vec![Track::new(path.to_path_buf())])
+    }
+}
+
+struct Job {
+    rank: usize,
+    dir: PathBuf,
+    mtime: Option<Mtime>,
+}
+
+struct Jobs {
+    pending: Vec<Job>,
+    /// Folders being scanned, which may still add more.
+    active: usize,
+}
+
+/// State shared by the threads walking the folders.
+struct Walk<'a> {
+    scanner: &'a Scanner,
+    roots: &'a [PathBuf],
+    index: &'a Index,
+    listings: Mutex<BTreeMap<PathBuf, Listing>>,
+    jobs: Mutex<Jobs>,
+    wake: Condvar,
+    visited: Mutex<HashSet<DirId>>,
+    summary: Mutex<Summary>,
+    sender: Sender<Vec<(Rank, Track)>>,
+    cancel: &'a AtomicBool,
+    files: &'a AtomicUsize,
+    folders: &'a AtomicUsize,
+}
+
+impl Walk<'_> {
+    fn run(&self) {
+        while let Some(job) = self.next_job() {
+            let children = self.visit(job);
+            let mut jobs = lock(&self.jobs);
+
+            // reversed, so the first subfolder is taken next and hits come early
+            jobs.pending.extend(children.into_iter().rev());
+            jobs.active -= 1;
+            self.wake.notify_all();
+        }
+    }
+
+    /// Waits for a folder to scan, or returns `None` once every folder is done.
+    fn next_job(&self) -> Option<Job> {
+        let mut jobs = lock(&self.jobs);
+
+        loop {
+            if self.cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            if let Some(job) = jobs.pending.pop() {
+                jobs.active += 1;
+                return Some(job);
+            }
+
+            if jobs.active == 0 {
+                return None;
+            }
+
+            jobs = self.wake.wait(jobs).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Sends the tracks in `job`'s folder and returns its subfolders.
+    fn visit(&self, job: Job) -> Vec<Job> {
+        let root = &self.roots[job.rank];
+        let mut summary = Summary::default();
+
+        let cached = self.index.get(&job.dir, job.mtime).cloned();
+        let listing = match cached {
+            Some(listing) => listing,
+            None => match Listing::read(&job.dir, job.mtime.unwrap_or_default(), &mut summary) {
+                Ok(listing) => listing,
+                Err(e) => {
+                    summary.unreadable.push((job.dir, e));
+                    lock(&self.summary).append(summary);
+                    return Vec::new();
+                }
+            },
+        };
+
+        if job.mtime.is_some()
+            && listing.is_storable()
+            && let Ok(key) = std::path::absolute(&job.dir)
+        {
+            lock(&self.listings).insert(key, listing.clone());
+        }
+
+        let mut children = Vec::new();
+
+        for name in &listing.dirs {
+            let path = job.dir.join(name);
+
+            if !self.scanner.admits(root, &path, &mut summary) {
+                continue;
+            }
+
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    summary.unreadable.push((path, e.to_string()));
+                    continue;
+                }
+            };
+
+            match dir_id(&path, &metadata) {
+                Some(id) if !lock(&self.visited).insert(id) => summary.loops.push(path),
+                _ => children.push(Job {
+                    rank: job.rank,
+                    mtime: mtime(&metadata),
+                    dir: path,
+                }),
+            }
+        }
+
+        let paths = listing
+            .files
+            .iter()
+            .map(|name| job.dir.join(name))
+            .filter(|path| {
+                self.scanner.admits(root, path, &mut summary) && self.scanner.wants(root, path)
+            })
+            .collect();
+        let tracks = split_by_cue_sheets(paths);
+
+        self.folders.fetch_add(1, Ordering::Relaxed);
+        self.files.fetch_add(tracks.len(), Ordering::Relaxed);
+        lock(&self.summary).append(summary);
+
+        if !tracks.is_empty() {
+            let _ = self.sender.send(
+                tracks
+                    .into_iter()
+                    .map(|track| ((job.rank, track.path.clone()), track))
+                    .collect(),
+            );
+        }
+
+        children
+    }
+}
+
+/// Collecting tracks from files, playlists and folders on background threads,
+/// handing them over as they turn up. Dropping the scan stops it.
+pub struct Scan {
+    receiver: Receiver<Vec<(Rank, Track)>>,
+    files: Arc<AtomicUsize>,
+    folders: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Scan {
+    pub fn spawn(roots: Vec<PathBuf>, scanner: Scanner) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let files = Arc::new(AtomicUsize::new(0));
+        let folders = Arc::new(AtomicUsize::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (files_thread, folders_thread, cancel_thread) = (
+            Arc::clone(&files),
+            Arc::clone(&folders),
+            Arc::clone(&cancel),
+        );
+
+        std::thread::spawn(move || {
+            scan(
+                &roots,
+                &scanner,
+                sender,
+                &cancel_thread,
+                &files_thread,
+                &folders_thread,
+            )
+        });
+
+        Self {
+            receiver,
+            files,
+            folders,
+            cancel,
+            done: false,
+        }
+    }
+
+    /// Whatever was found since the last call.
+    pub fn poll(&mut self) -> Vec<(Rank, Track)> {
+        let mut found = Vec::new();
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(batch) => found.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Whether everything has been found and handed over by `poll`.
+    pub const fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Tracks and folders found so far.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.files.load(Ordering::Relaxed),
+            self.folders.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Blocks until the scan is done, returning everything in rank order.
+    pub fn wait(self) -> Vec<Track> {
+        into_order(self.receiver.iter().flatten().collect())
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+fn scan(
+    roots: &[PathBuf],
+    scanner: &Scanner,
+    sender: Sender<Vec<(Rank, Track)>>,
+    cancel: &AtomicBool,
+    files: &AtomicUsize,
+    folders: &AtomicUsize,
+) {
+    let mut index = Index::load().unwrap_or_else(|e| {
+        log(Log::Warning, format!("{e}, reading every folder again"));
+        Index::default()
+    });
+    let mut jobs = Vec::new();
+    let mut dir_roots = Vec::new();
+    let mut visited = HashSet::new();
+
+    for (rank, root) in roots.iter().enumerate() {
+        let metadata = match std::fs::metadata(root) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log(Log::Warning, format!("{}: {e}", root.display()));
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            visited.extend(dir_id(root, &metadata));
+            dir_roots.push(root.clone());
+            jobs.push(Job {
+                rank,
+                dir: root.clone(),
+                mtime: mtime(&metadata),
+            });
+            continue;
+        }
+
+        match expand(root) {
+            Ok(tracks) => {
+                files.fetch_add(tracks.len(), Ordering::Relaxed);
+                let _ = sender.send(
+                    tracks
+                        .into_iter()
+                        .map(|track| ((rank, PathBuf::new()), track))
+                        .collect(),
+                );
+            }
+            Err(e) => log(Log::Warning, format!("{}: {e}", root.display())),
+        }
+    }
+
+    // popped from the back, so the first root goes first
+    jobs.reverse();
+
+    let walk = Walk {
+        scanner,
+        roots,
+        index: &index,
+        listings: Mutex::new(BTreeMap::new()),
+        jobs: Mutex::new(Jobs {
+            pending: jobs,
+            active: 0,
+        }),
+        wake: Condvar::new(),
+        visited: Mutex::new(visited),
+        summary: Mutex::new(Summary::default()),
+        sender,
+        cancel,
+        files,
+        folders,
+    };
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| walk.run());
+        }
+    });
+
+    if cancel.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Walk {
+        listings, summary, ..
+    } = walk;
+
+    summary
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .report();
+    log(
+        Log::Info,
+        format!(
+            "{} file(s) found in {} folder(s)",
+            files.load(Ordering::Relaxed),
+            folders.load(Ordering::Relaxed)
+        ),
+    );
+
+    index.update(
+        &dir_roots,
+        listings
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+
+    if let Err(e) = index.save() {
+        log(Log::Warning, format!("failed to save folder index ({e})"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        Glob::new(pattern).matches(name, path)
+    }
+
+    #[test]
+    fn star_stays_within_a_folder() {
+        assert!(matches("*.mp3", "album/song.mp3"));
+        assert!(matches("album/*.mp3", "album/song.mp3"));
+        assert!(!matches("album/*.mp3", "album/disc 1/song.mp3"));
+        assert!(!matches("*/song.mp3", "a/b/song.mp3"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn double_star_crosses_folders() {
+        assert!(matches("album/**.mp3", "album/disc 1/song.mp3"));
+        assert!(matches("**/song.mp3", "a/b/song.mp3"));
+        // `**/` also stands for no folder at all
+        assert!(matches("**/song.mp3", "song.mp3"));
+        assert!(matches("a/**/song.mp3", "a/song.mp3"));
+        assert!(matches("a/**/song.mp3", "a/b/c/song.mp3"));
+        assert!(!matches("a/**/song.mp3", "b/song.mp3"));
+    }
+
+    #[test]
+    fn question_mark_is_one_character_but_not_a_slash() {
+        assert!(matches("track?.mp3", "track1.mp3"));
+        assert!(!matches("track?.mp3", "track10.mp3"));
+        assert!(!matches("a?b/*", "a/b/c"));
+    }
+
+    #[test]
+    fn names_and_paths() {
+        // without a `/` only the name is matched, wherever the file is
+        assert!(matches("live*", "concerts/live at home.mp3"));
+        assert!(!matches("concerts*", "concerts/live at home.mp3"));
+        // with one the whole relative path has to match
+        assert!(!matches("live/*.mp3", "concerts/live/a.mp3"));
+        assert!(matches("**/live/*.mp3", "concerts/live/a.mp3"));
+    }
+
+    #[test]
+    fn relative_paths_use_slashes() {
+        let (name, relative) = names(Path::new("/music"), Path::new("/music/a/b.mp3"));
+
+        assert_eq!(name, "b.mp3");
+        assert_eq!(relative, "a/b.mp3");
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{dirs, queue::Queue, strerr::Strerr};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where playback stood when the player was last closed, kept in the state directory.
+#[derive(PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Folders the queue was collected from. A session only resumes for the same folders.
+    pub roots: Vec<PathBuf>,
+    /// Seconds into the current track.
+    pub position: f64,
+    pub speed: f32,
+    pub volume: f32,
+    pub queue: Queue,
+}
+
+impl Session {
+    /// How often the session is saved while playing, so a crash loses little.
+    pub const SAVE_SECS: u64 = 30;
+
+    fn path() -> Option<PathBuf> {
+        dirs::state_dir().map(|dir| dir.join("session.toml"))
+    }
+
+    pub fn load() -> Result<Option<Self>, String> {
+        let Some(path) = Self::path() else {
+            return Ok(None);
+        };
+
+        let Some(session) = dirs::read_toml::<Self>(&path)? else {
+            return Ok(None);
+        };
+
+        if !session.queue.is_consistent() {
+            return Err(format!("{}: inconsistent queue", path.display()));
+        }
+
+        Ok(Some(session))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or("no state directory")?;
+        let text = toml::to_string(self).strerr()?;
+
+        dirs::write_atomic(&path, text)
+    }
+}
@@ -75,4 +75,14 @@ impl TimedSmooth {
         self.smooth.shift_set(to, self.ratio_curved());
         self.last_modified = self.now_cached;
     }
+
+    /// The value being transitioned to.
+    pub const fn target(&self) -> f32 {
+        self.smooth.to
+    }
+
+    /// Moves to `value` without a transition.
+    pub const fn reset(&mut self, value: f32) {
+        self.smooth = Smooth::new(value);
+    }
 }
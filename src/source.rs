@@ -0,0 +1,196 @@
+use crate::{
+    Strerr,
+    gain::ReplayGain,
+    log::{Log, log},
+    playback::Sample,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use symphonia::core::{
+    audio::{AudioBufferRef, Signal},
+    codecs::{CODEC_TYPE_NULL, Decoder},
+    formats::{FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+};
+
+pub struct Source {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub total_frames: u64,
+    pub replay_gain: ReplayGain,
+}
+
+impl Source {
+    pub fn open<P>(path: P) -> Result<Self, String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let src = std::fs::File::open(path).strerr()?;
+        let mss = MediaSourceStream::new(Box::new(src), Default::default());
+        let probed = symphonia::default::get_probe()
+            .format(
+                &Default::default(),
+                mss,
+                &Default::default(),
+                &Default::default(),
+            )
+            .strerr()?;
+
+        let mut format = probed.format;
+        let mut metadata = probed.metadata;
+
+        // tags may come from both a container header (e.g. ID3v2) and the format itself
+        let replay_gain = {
+            let mut tags = Vec::new();
+
+            if let Some(metadata) = metadata.get()
+                && let Some(revision) = metadata.current()
+            {
+                tags.extend_from_slice(revision.tags());
+            }
+
+            if let Some(revision) = format.metadata().current() {
+                tags.extend_from_slice(revision.tags());
+            }
+
+            ReplayGain::from_tags(&tags)
+        };
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or("no supported audio tracks")?;
+
+        let sample_rate = track.codec_params.sample_rate.ok_or("no sample rate")?;
+        let channels = track.codec_params.channels.ok_or("no channels")?.count();
+        let total_frames = track.codec_params.n_frames.ok_or("no frames")?;
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &Default::default())
+            .strerr()?;
+
+        let track_id = track.id;
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            channels,
+            total_frames,
+            replay_gain,
+        })
+    }
+
+    /// Decodes the next packet and appends its interleaved samples to `out`.
+    /// Returns the number of frames appended.
+    pub fn decode_next(&mut self, out: &mut Vec<Sample>) -> Result<usize, String> {
+        type SymErr = symphonia::core::errors::Error;
+
+        loop {
+            let packet = self.format.next_packet().strerr()?;
+
+            while !self.format.metadata().is_latest() {
+                self.format.metadata().pop();
+            }
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let frames = decoded.frames();
+                    let channels = spec.channels.count();
+
+                    // MP3 decodes to floats already, FLAC to integers
+                    let converted;
+                    let buffer = match decoded {
+                        AudioBufferRef::F32(ref cow) => cow.as_ref(),
+                        _ => {
+                            let mut buffer = decoded.make_equivalent::<Sample>();
+                            decoded.convert(&mut buffer);
+                            converted = buffer;
+                            &converted
+                        }
+                    };
+
+                    out.reserve(frames * channels);
+
+                    for frame in 0..frames {
+                        for channel in 0..channels {
+                            out.push(buffer.chan(channel)[frame]);
+                        }
+                    }
+
+                    return Ok(frames);
+                }
+                Err(SymErr::IoError(e)) => log(Log::Warning, format!("IoError says \"{e}\"")),
+                Err(SymErr::DecodeError(e)) => {
+                    log(Log::Warning, format!("DecodeError says \"{e}\""))
+                }
+                Err(e) => {
+                    return Err(e).strerr();
+                }
+            }
+        }
+    }
+
+    /// Seeks to `frame` and returns the frame the next decoded packet starts at,
+    /// which is never past `frame`.
+    pub fn seek(&mut self, frame: u64) -> Result<u64, String> {
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::TimeStamp {
+                    ts: frame,
+                    track_id: self.track_id,
+                },
+            )
+            .strerr()?;
+
+        self.decoder.reset();
+
+        Ok(seeked.actual_ts)
+    }
+
+    /// Decodes from `frame`, where the last seek left off, until `last`, handing
+    /// each block to `f` with the frame it starts at. Returns `false` if `cancel`
+    /// was raised before the end.
+    pub fn for_each_block<F>(
+        &mut self,
+        mut frame: u64,
+        last: u64,
+        cancel: &AtomicBool,
+        mut f: F,
+    ) -> bool
+    where
+        F: FnMut(u64, &[Sample]),
+    {
+        let mut block = Vec::new();
+
+        // the end of the stream surfaces as an error, so we stop at `last` instead
+        while frame < last {
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            block.clear();
+
+            match self.decode_next(&mut block) {
+                Ok(decoded) => {
+                    f(frame, &block);
+                    frame += decoded as u64;
+                }
+                Err(_) => break,
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+
+/// Shape of the speed bar on either side of the 1x detent.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Evenly spaced in octaves, so halving and doubling are equally far from 1x.
+    Exponential,
+    Linear,
+}
+
+impl Curve {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Exponential => "exp",
+            Self::Linear => "linear",
+        }
+    }
+
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Exponential => Self::Linear,
+            Self::Linear => Self::Exponential,
+        }
+    }
+}
+
+/// Maps a position on the speed bar (`0.0..=1.0`) to a playback speed.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedMap {
+    pub min: f32,
+    pub max: f32,
+    pub curve: Curve,
+    /// Where 1x sits on the bar.
+    pub detent: f32,
+    /// Step that clicks and drags snap to, if any.
+    pub snap: Option<f32>,
+}
+
+impl SpeedMap {
+    pub const SNAP: f32 = 0.05;
+    /// Distance from the detent within which clicks and drags stick to 1x.
+    const DETENT_WIDTH: f32 = 0.01;
+
+    /// 0.25x to 8x on an exponential curve, with 1x at 40%.
+    pub const fn new() -> Self {
+        Self {
+            min: 0.25,
+            max: 8.0,
+            curve: Curve::Exponential,
+            detent: 0.4,
+            snap: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.min > 0.0 && self.min <= 1.0) {
+            return Err(format!(
+                "minimum speed must be in (0, 1], found {}",
+                self.min
+            ));
+        }
+
+        if !self.max.is_finite() || self.max < 1.0 {
+            return Err(format!(
+                "maximum speed must be at least 1, found {}",
+                self.max
+            ));
+        }
+
+        if !(self.detent > 0.0 && self.detent < 1.0) {
+            return Err(format!("detent must be in (0, 1), found {}", self.detent));
+        }
+
+        if let Some(step) = self.snap
+            && (!step.is_finite() || step <= 0.0)
+        {
+            return Err(format!("snap step must be positive, found {step}"));
+        }
+
+        Ok(())
+    }
+
+    pub fn speed(&self, fraction: f32) -> f32 {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let (from, to, t) = if fraction < self.detent {
+            (self.min, 1.0, fraction / self.detent)
+        } else {
+            (
+                1.0,
+                self.max,
+                (fraction - self.detent) / (1.0 - self.detent).max(f32::EPSILON),
+            )
+        };
+
+        match self.curve {
+            Curve::Exponential => from * (to / from).powf(t),
+            Curve::Linear => from + (to - from) * t,
+        }
+    }
+
+    pub fn fraction(&self, speed: f32) -> f32 {
+        let speed = speed.clamp(self.min, self.max);
+        let (from, to, left, right) = if speed < 1.0 {
+            (self.min, 1.0, 0.0, self.detent)
+        } else {
+            (1.0, self.max, self.detent, 1.0)
+        };
+
+        let t = match self.curve {
+            _ if from == to => 0.0,
+            Curve::Exponential => (speed / from).ln() / (to / from).ln(),
+            Curve::Linear => (speed - from) / (to - from),
+        };
+
+        left + (right - left) * t
+    }
+
+    /// Sticks a bar position to the detent when close, otherwise to the nearest
+    /// `snap` step if snapping is on.
+    pub fn snap(&self, fraction: f32) -> f32 {
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        if (fraction - self.detent).abs() < Self::DETENT_WIDTH {
+            return self.detent;
+        }
+
+        match self.snap {
+            Some(step) => self.fraction((self.speed(fraction) / step).round() * step),
+            None => fraction,
+        }
+    }
+}
+
+impl Default for SpeedMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    dsp::{Processor, to_amplitude},
+    eq::{Biquad, Coefficients},
+    playback::Sample,
+    smooth::Smooth,
+};
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+/// Bauer-style headphone crossfeed: each ear also hears a low-passed,
+/// attenuated copy of the opposite channel. Only affects stereo.
+pub struct Crossfeed {
+    pub cutoff: f32,
+    pub feed_db: f32,
+    lowpassed: [Sample; 2],
+}
+
+impl Crossfeed {
+    pub const fn new() -> Self {
+        Self {
+            cutoff: 700.0,
+            feed_db: 4.5,
+            lowpassed: [0.0; 2],
+        }
+    }
+}
+
+impl Processor for Crossfeed {
+    fn name(&self) -> &'static str {
+        "crossfeed"
+    }
+
+    fn reset(&mut self) {
+        self.lowpassed = [0.0; 2];
+    }
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        if channels != 2 {
+            return;
+        }
+
+        let alpha = 1.0 - (-2.0 * PI * self.cutoff / sample_rate as f32).exp();
+        let feed = to_amplitude(-self.feed_db);
+        // keeps centered (mono) content at its original level
+        let normalize = 1.0 / (1.0 + feed);
+
+        for frame in block.chunks_exact_mut(2) {
+            for (lowpassed, &sample) in self.lowpassed.iter_mut().zip(frame.iter()) {
+                *lowpassed += alpha * (sample - *lowpassed);
+            }
+
+            let [left, right] = [frame[0], frame[1]];
+            frame[0] = (left + feed * self.lowpassed[1]) * normalize;
+            frame[1] = (right + feed * self.lowpassed[0]) * normalize;
+        }
+    }
+}
+
+/// Mid/side width control, from mono (`0.0`) through unchanged (`1.0`) to `2.0`.
+pub struct StereoWidth {
+    width: Smooth,
+}
+
+impl StereoWidth {
+    pub const MAX: f32 = 2.0;
+
+    pub const fn new() -> Self {
+        Self {
+            width: Smooth::new(1.0),
+        }
+    }
+
+    pub const fn set_width(&mut self, width: f32) {
+        self.width.set(width.clamp(0.0, Self::MAX));
+    }
+}
+
+impl Processor for StereoWidth {
+    fn name(&self) -> &'static str {
+        "stereo width"
+    }
+
+    fn process(&mut self, block: &mut [Sample], _: u32, channels: usize) {
+        if channels == 2 {
+            let frames = block.len() / 2;
+
+            for (frame, samples) in block.chunks_exact_mut(2).enumerate() {
+                let width = self.width.interpolate(frame as f32 / frames as f32);
+                let mid = (samples[0] + samples[1]) / 2.0;
+                let side = (samples[0] - samples[1]) / 2.0 * width;
+                samples[0] = mid + side;
+                samples[1] = mid - side;
+            }
+        }
+
+        self.width.stabilize();
+    }
+}
+
+/// Karaoke mode: cancels everything panned dead center by playing `L - R` on
+/// both channels. Optionally keeps the lows below `crossover` untouched, since
+/// bass and kick drums are usually centered too.
+pub struct VocalRemover {
+    pub preserve_lows: bool,
+    pub crossover: f32,
+    /// Linkwitz-Riley (two cascaded Butterworth stages) low and high band per channel.
+    lows: [[Biquad; 2]; 2],
+    highs: [[Biquad; 2]; 2],
+}
+
+impl VocalRemover {
+    pub fn new() -> Self {
+        Self {
+            preserve_lows: true,
+            crossover: 200.0,
+            lows: Default::default(),
+            highs: Default::default(),
+        }
+    }
+}
+
+impl Processor for VocalRemover {
+    fn name(&self) -> &'static str {
+        "vocal remover"
+    }
+
+    fn reset(&mut self) {
+        self.lows = Default::default();
+        self.highs = Default::default();
+    }
+
+    fn process(&mut self, block: &mut [Sample], sample_rate: u32, channels: usize) {
+        if channels != 2 {
+            return;
+        }
+
+        let lowpass = Coefficients::lowpass(self.crossover, FRAC_1_SQRT_2, sample_rate);
+        let highpass = Coefficients::highpass(self.crossover, FRAC_1_SQRT_2, sample_rate);
+
+        for frame in block.chunks_exact_mut(2) {
+            if !self.preserve_lows {
+                let side = frame[0] - frame[1];
+                frame.fill(side);
+                continue;
+            }
+
+            let mut split = [(0.0, 0.0); 2];
+
+            for (channel, &sample) in frame.iter().enumerate() {
+                let [low_1, low_2] = &mut self.lows[channel];
+                let [high_1, high_2] = &mut self.highs[channel];
+                split[channel] = (
+                    low_2.process(&lowpass, low_1.process(&lowpass, sample)),
+                    high_2.process(&highpass, high_1.process(&highpass, sample)),
+                );
+            }
+
+            let [(low_left, high_left), (low_right, high_right)] = split;
+            let side = high_left - high_right;
+            frame[0] = low_left + side;
+            frame[1] = low_right + side;
+        }
+    }
+}
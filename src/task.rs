@@ -0,0 +1,57 @@
+use crate::log::{Log, log};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver},
+};
+
+/// Work running on a background thread. Dropping the task asks the work to stop
+/// through the flag it is given.
+pub struct Task<T> {
+    value: Option<T>,
+    receiver: Receiver<T>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<T> Task<T>
+where
+    T: Send + 'static,
+{
+    pub fn spawn<F>(what: &'static str, work: F) -> Self
+    where
+        F: FnOnce(&AtomicBool) -> Result<Option<T>, String> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel_thread = Arc::clone(&cancel);
+
+        std::thread::spawn(move || match work(&cancel_thread) {
+            Ok(Some(value)) => {
+                let _ = sender.send(value);
+            }
+            Ok(None) => (),
+            Err(e) => log(Log::Warning, format!("{what} failed ({e})")),
+        });
+
+        Self {
+            value: None,
+            receiver,
+            cancel,
+        }
+    }
+
+    /// Returns the result once the work has finished.
+    pub fn poll(&mut self) -> Option<&T> {
+        if let Ok(value) = self.receiver.try_recv() {
+            self.value = Some(value);
+        }
+
+        self.value.as_ref()
+    }
+}
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
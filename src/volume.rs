@@ -0,0 +1,64 @@
+use crate::dsp::{to_amplitude, to_db};
+use serde::{Deserialize, Serialize};
+
+/// Maps a position on the volume bar (`0.0..=1.0`) to a linear gain.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeLaw {
+    Linear,
+    /// Evenly spaced in dB over `RANGE_DB`, with the far left muting.
+    Decibel,
+    Cubic,
+}
+
+impl VolumeLaw {
+    const RANGE_DB: f32 = 60.0;
+    /// Gain the volume crosshair sits at.
+    pub const ORIGIN_DB: f32 = -12.0;
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Decibel => "dB",
+            Self::Cubic => "cubic",
+        }
+    }
+
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Linear => Self::Decibel,
+            Self::Decibel => Self::Cubic,
+            Self::Cubic => Self::Linear,
+        }
+    }
+
+    pub fn gain(self, fraction: f32) -> f32 {
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => fraction,
+            Self::Decibel if fraction == 0.0 => 0.0,
+            Self::Decibel => to_amplitude((fraction - 1.0) * Self::RANGE_DB),
+            Self::Cubic => fraction.powi(3),
+        }
+    }
+
+    pub fn fraction(self, gain: f32) -> f32 {
+        let gain = gain.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => gain,
+            Self::Decibel if gain == 0.0 => 0.0,
+            Self::Decibel => (1.0 + to_db(gain) / Self::RANGE_DB).max(0.0),
+            Self::Cubic => gain.cbrt(),
+        }
+    }
+
+    pub fn origin(self) -> f32 {
+        self.fraction(Self::origin_gain())
+    }
+
+    pub fn origin_gain() -> f32 {
+        to_amplitude(Self::ORIGIN_DB)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{playback::Sample, strerr::Strerr};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const HEADER_LEN: u32 = 44;
+const FORMAT_FLOAT: u16 = 3;
+
+/// Streams interleaved 32-bit float samples into a WAV file. The sizes in the
+/// header are filled in by `finish`.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    channels: u16,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create<P>(path: P, sample_rate: u32, channels: usize) -> Result<Self, String>
+    where
+        P: AsRef<Path>,
+    {
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path).strerr()?),
+            sample_rate,
+            channels: channels as u16,
+            data_len: 0,
+        };
+
+        writer.write_header()?;
+
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let block_align = self.channels * size_of::<Sample>() as u16;
+        let header: Vec<u8> = [
+            b"RIFF".as_slice(),
+            &(HEADER_LEN - 8 + self.data_len).to_le_bytes(),
+            b"WAVEfmt ",
+            &16_u32.to_le_bytes(),
+            &FORMAT_FLOAT.to_le_bytes(),
+            &self.channels.to_le_bytes(),
+            &self.sample_rate.to_le_bytes(),
+            &(self.sample_rate * block_align as u32).to_le_bytes(),
+            &block_align.to_le_bytes(),
+            &(8 * size_of::<Sample>() as u16).to_le_bytes(),
+            b"data",
+            &self.data_len.to_le_bytes(),
+        ]
+        .concat();
+
+        self.file.write_all(&header).strerr()
+    }
+
+    pub fn write(&mut self, samples: &[Sample]) -> Result<(), String> {
+        self.data_len = u32::try_from(size_of_val(samples))
+            .ok()
+            .and_then(|len| self.data_len.checked_add(len))
+            .filter(|&len| len <= u32::MAX - HEADER_LEN)
+            .ok_or("WAV file would exceed 4 GiB")?;
+
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes()).strerr()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(0)).strerr()?;
+        self.write_header()?;
+        self.file.flush().strerr()
+    }
+}
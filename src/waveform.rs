@@ -0,0 +1,106 @@
+use crate::{
+    cache,
+    log::{Log, log},
+    playback::Sample,
+    source::Source,
+    task::Task,
+};
+use std::{path::Path, sync::atomic::AtomicBool};
+
+/// Minimum and maximum sample of each bucket, across all channels.
+pub type Peaks = Box<[(Sample, Sample)]>;
+
+const CACHE_KIND: &str = "waveform";
+
+/// Scans `span` seconds of `path` into `buckets` peaks in the background, or
+/// loads them from the cache. Only whole files are cached, cue sheet tracks are
+/// short enough to scan every time.
+pub fn spawn<P>(path: P, span: (f64, Option<f64>), buckets: usize) -> Task<Peaks>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().to_path_buf();
+    let whole = span == (0.0, None);
+
+    Task::spawn("waveform scan", move |cancel| {
+        if whole
+            && let Some(peaks) = cache::load(CACHE_KIND, &path).and_then(|bytes| decode(&bytes))
+            && peaks.len() == buckets
+        {
+            return Ok(Some(peaks));
+        }
+
+        let Some(peaks) = scan(&path, span, buckets, cancel)? else {
+            return Ok(None);
+        };
+
+        if whole && let Err(e) = cache::store(CACHE_KIND, &path, &encode(&peaks)) {
+            log(Log::Warning, format!("failed to cache waveform ({e})"));
+        }
+
+        Ok(Some(peaks))
+    })
+}
+
+fn scan(
+    path: &Path,
+    (start, end): (f64, Option<f64>),
+    buckets: usize,
+    cancel: &AtomicBool,
+) -> Result<Option<Peaks>, String> {
+    let mut source = Source::open(path)?;
+    let channels = source.channels;
+    let rate = source.sample_rate as f64;
+    let first = ((start * rate) as u64).min(source.total_frames);
+    let last = end.map_or(source.total_frames, |end| {
+        ((end * rate) as u64).clamp(first, source.total_frames)
+    });
+    let frames = (last - first).max(1);
+    let mut peaks = vec![(0.0, 0.0); buckets];
+    let from = match first {
+        0 => 0,
+        first => source.seek(first)?,
+    };
+
+    let done = source.for_each_block(from, last, cancel, |start, block| {
+        for (frame, samples) in (start..).zip(block.chunks_exact(channels)) {
+            if (first..last).contains(&frame) {
+                let bucket = ((frame - first) * buckets as u64 / frames) as usize;
+                let (min, max) = &mut peaks[bucket.min(buckets - 1)];
+
+                for &sample in samples {
+                    *min = sample.min(*min);
+                    *max = sample.max(*max);
+                }
+            }
+        }
+    });
+
+    if !done {
+        return Ok(None);
+    }
+
+    Ok(Some(peaks.into_boxed_slice()))
+}
+
+fn encode(peaks: &Peaks) -> Vec<u8> {
+    peaks
+        .iter()
+        .flat_map(|&(min, max)| min.to_le_bytes().into_iter().chain(max.to_le_bytes()))
+        .collect()
+}
+
+fn decode(bytes: &[u8]) -> Option<Peaks> {
+    if !bytes.len().is_multiple_of(8) {
+        return None;
+    }
+
+    let sample = |bytes: &[u8]| Sample::from_le_bytes(bytes.try_into().unwrap_or_default());
+
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|pair| (sample(&pair[..4]), sample(&pair[4..])))
+            .collect(),
+    )
+}